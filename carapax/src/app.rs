@@ -50,6 +50,9 @@ impl App {
     }
 
     /// Returns a future that will run the app
+    ///
+    /// Use `UpdateMethod::shutdown_signal()` in order to stop the app gracefully:
+    /// future resolves when running handlers are finished.
     pub fn run(self, api: Api, method: UpdateMethod) -> impl Future<Item = (), Error = ()> {
         handle_updates(method, Dispatcher::new(api, self.handlers, self.error_strategy))
     }
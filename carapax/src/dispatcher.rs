@@ -3,7 +3,7 @@ use crate::{
     handler::{BoxedHandler, HandlerFuture, HandlerResult},
 };
 use failure::Error;
-use futures::{Async, Future, Poll};
+use futures::{future, sync::mpsc, Async, Future, Poll, Stream};
 use std::sync::Arc;
 use tgbot::{types::Update, Api, UpdateHandler};
 
@@ -20,6 +20,8 @@ pub(crate) struct Dispatcher {
     api: Api,
     handlers: Arc<Vec<BoxedHandler>>,
     error_strategy: ErrorStrategy,
+    // each spawned future holds a sender, so receiver ends when all of them are finished
+    in_flight: Option<(mpsc::Sender<()>, mpsc::Receiver<()>)>,
 }
 
 impl Dispatcher {
@@ -28,6 +30,7 @@ impl Dispatcher {
             api,
             handlers: Arc::new(handlers),
             error_strategy,
+            in_flight: Some(mpsc::channel(0)),
         }
     }
 
@@ -40,13 +43,22 @@ impl Dispatcher {
 
 impl UpdateHandler for Dispatcher {
     fn handle(&mut self, update: Update) {
-        tokio_executor::spawn(self.dispatch(update).then(|r| {
+        let in_flight = self.in_flight.as_ref().map(|(sender, _)| sender.clone());
+        tokio_executor::spawn(self.dispatch(update).then(move |r| {
             if let Err((e, _context)) = r {
                 log::error!("Failed to dispatch update: {:?}", e);
             }
+            drop(in_flight);
             Ok(())
         }));
     }
+
+    fn drain(&mut self) -> Box<dyn Future<Item = (), Error = ()> + Send> {
+        match self.in_flight.take() {
+            Some((_, receiver)) => Box::new(receiver.for_each(|()| Ok(()))),
+            None => Box::new(future::ok(())),
+        }
+    }
 }
 
 struct HandlersQueue {
@@ -261,4 +273,40 @@ mod tests {
         let context = dispatcher.dispatch(update.clone()).wait().unwrap();
         assert_eq!(context.get::<Counter>().get_calls(), 1);
     }
+
+    #[test]
+    fn drain() {
+        let update: Update = serde_json::from_value(serde_json::json!(
+            {
+                "update_id": 1,
+                "message": {
+                    "message_id": 1111,
+                    "date": 0,
+                    "from": {"id": 1, "is_bot": false, "first_name": "test"},
+                    "chat": {"id": 1, "type": "private", "first_name": "test"},
+                    "text": "test"
+                }
+            }
+        ))
+        .unwrap();
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let handler_calls = calls.clone();
+        let mut dispatcher = Dispatcher::new(
+            Api::new("token").unwrap(),
+            vec![HandlerWrapper::boxed(FnHandler::from(
+                move |_context: &mut Context, _update: Update| {
+                    handler_calls.fetch_add(1, Ordering::SeqCst);
+                },
+            ))],
+            ErrorStrategy::Abort,
+        );
+        tokio::runtime::current_thread::block_on_all(future::lazy(move || {
+            dispatcher.handle(update.clone());
+            dispatcher.handle(update);
+            dispatcher.drain()
+        }))
+        .unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
 }
@@ -0,0 +1,209 @@
+use crate::{
+    api::{Api, ApiFuture},
+    methods::Method,
+};
+use failure::Error;
+use futures::{stream, Future, Poll, Stream};
+use serde::de::DeserializeOwned;
+use std::cmp::max;
+
+impl Api {
+    /// Executes methods concurrently
+    ///
+    /// Returns results in the same order as methods,
+    /// a failed method does not stop execution of other methods.
+    ///
+    /// # Arguments
+    ///
+    /// * methods - Methods to execute
+    /// * concurrency - Maximum number of methods executed at the same time (at least 1)
+    pub fn execute_all<I, M>(&self, methods: I, concurrency: usize) -> ApiFuture<Vec<Result<M::Response, Error>>>
+    where
+        I: IntoIterator<Item = M>,
+        I::IntoIter: Send + 'static,
+        M: Method + Send + 'static,
+        M::Response: DeserializeOwned + Send + 'static,
+    {
+        ApiFuture {
+            inner: Box::new(
+                self.execute_indexed(methods.into_iter())
+                    .buffered(max(concurrency, 1))
+                    .map(|(_, result)| result)
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Executes methods concurrently and yields results as soon as they are ready
+    ///
+    /// Each result is tagged with an index of a method,
+    /// a failed method does not stop execution of other methods.
+    ///
+    /// # Arguments
+    ///
+    /// * methods - Methods to execute
+    /// * concurrency - Maximum number of methods executed at the same time (at least 1)
+    pub fn execute_all_unordered<I, M>(&self, methods: I, concurrency: usize) -> BatchStream<M::Response>
+    where
+        I: IntoIterator<Item = M>,
+        I::IntoIter: Send + 'static,
+        M: Method + Send + 'static,
+        M::Response: DeserializeOwned + Send + 'static,
+    {
+        BatchStream {
+            inner: Box::new(
+                self.execute_indexed(methods.into_iter())
+                    .buffer_unordered(max(concurrency, 1)),
+            ),
+        }
+    }
+
+    fn execute_indexed<I, M>(
+        &self,
+        methods: I,
+    ) -> impl Stream<Item = impl Future<Item = (usize, Result<M::Response, Error>), Error = Error>, Error = Error>
+    where
+        I: Iterator<Item = M> + Send + 'static,
+        M: Method + Send + 'static,
+        M::Response: DeserializeOwned + Send + 'static,
+    {
+        let api = self.clone();
+        // methods are executed lazily, so a request is not sent until there is a free slot
+        stream::iter_ok(methods.enumerate())
+            .map(move |(idx, method)| api.execute(method).then(move |result| Ok((idx, result))))
+    }
+}
+
+/// A stream of results of methods tagged with an index
+///
+/// See [Api::execute_all_unordered()](struct.Api.html#method.execute_all_unordered)
+#[must_use = "streams do nothing unless polled"]
+pub struct BatchStream<T> {
+    inner: Box<dyn Stream<Item = (usize, Result<T, Error>), Error = Error> + Send>,
+}
+
+impl<T> Stream for BatchStream<T> {
+    type Item = (usize, Result<T, Error>);
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        self.inner.poll()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        executor::Executor,
+        methods::{GetChat, GetMe},
+        request::Request,
+        types::Chat,
+    };
+    use futures::{future, sync::oneshot};
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    type Pending = Arc<Mutex<HashMap<String, oneshot::Sender<()>>>>;
+
+    struct ExecutorMock {
+        active: Arc<Mutex<(usize, usize)>>,
+        pending: Pending,
+    }
+
+    impl Executor for ExecutorMock {
+        fn execute(&self, req: Request) -> Box<dyn Future<Item = Vec<u8>, Error = Error> + Send> {
+            let chat_id = req.get_chat_id().map(|chat_id| chat_id.to_string()).unwrap_or_default();
+            {
+                let mut active = self.active.lock().unwrap();
+                active.0 += 1;
+                active.1 = max(active.0, active.1);
+            }
+            let (tx, rx) = oneshot::channel();
+            self.pending.lock().unwrap().insert(chat_id.clone(), tx);
+            let active = self.active.clone();
+            Box::new(rx.from_err().and_then(move |()| {
+                active.lock().unwrap().0 -= 1;
+                if chat_id == "2" {
+                    Ok(br#"{"ok": false, "description": "Chat not found", "error_code": 400}"#.to_vec())
+                } else {
+                    Ok(format!(
+                        r#"{{"ok": true, "result": {{"id": {}, "type": "private", "first_name": "test"}}}}"#,
+                        chat_id
+                    )
+                    .into_bytes())
+                }
+            }))
+        }
+    }
+
+    fn create_api() -> (Api, Arc<Mutex<(usize, usize)>>, Pending) {
+        let active = Arc::new(Mutex::new((0, 0)));
+        let pending = Arc::new(Mutex::new(HashMap::new()));
+        let executor = ExecutorMock {
+            active: active.clone(),
+            pending: pending.clone(),
+        };
+        (Api::with_executor("token", executor), active, pending)
+    }
+
+    /// Polls a future until all pending requests are completed in a given order
+    fn complete<F: Future>(mut f: F, pending: &Pending, order: &[&str]) -> F::Item
+    where
+        F::Error: std::fmt::Debug,
+    {
+        future::poll_fn(move || loop {
+            if let futures::Async::Ready(item) = f.poll().unwrap() {
+                return Ok::<_, ()>(futures::Async::Ready(item));
+            }
+            let mut pending = pending.lock().unwrap();
+            match order.iter().find(|chat_id| pending.contains_key(**chat_id)) {
+                Some(chat_id) => pending.remove(*chat_id).unwrap().send(()).unwrap(),
+                None => panic!("No pending requests"),
+            }
+        })
+        .wait()
+        .unwrap()
+    }
+
+    #[test]
+    fn execute_all() {
+        let (api, active, pending) = create_api();
+        let methods = vec![GetChat::new(1), GetChat::new(2), GetChat::new(3), GetChat::new(4)];
+        let results = complete(api.execute_all(methods, 2), &pending, &["4", "3", "2", "1"]);
+        assert_eq!(results.len(), 4);
+        let ids: Vec<Option<i64>> = results
+            .iter()
+            .map(|result| match result {
+                Ok(Chat::Private(chat)) => Some(chat.id),
+                Ok(chat) => panic!("Unexpected chat: {:?}", chat),
+                Err(_) => None,
+            })
+            .collect();
+        assert_eq!(ids, vec![Some(1), None, Some(3), Some(4)]);
+        assert_eq!(active.lock().unwrap().1, 2);
+    }
+
+    #[test]
+    fn execute_all_unordered() {
+        let (api, active, pending) = create_api();
+        let methods = vec![GetChat::new(1), GetChat::new(2), GetChat::new(3)];
+        let results = complete(
+            api.execute_all_unordered(methods, 3).collect(),
+            &pending,
+            &["3", "2", "1"],
+        );
+        let results: Vec<(usize, bool)> = results.into_iter().map(|(idx, result)| (idx, result.is_ok())).collect();
+        assert_eq!(results, vec![(2, true), (1, false), (0, true)]);
+        assert_eq!(active.lock().unwrap().1, 3);
+    }
+
+    #[test]
+    fn execute_all_empty() {
+        let (api, _, _) = create_api();
+        let results = api.execute_all(Vec::<GetMe>::new(), 0).wait().unwrap();
+        assert!(results.is_empty());
+    }
+}
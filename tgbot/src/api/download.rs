@@ -0,0 +1,344 @@
+use crate::{
+    api::ApiFuture,
+    blocking::{blocking, poll_blocking},
+};
+use failure::Error;
+use futures::{stream, try_ready, Async, Future, Poll, Stream};
+use std::{
+    fs::{remove_file, rename, File},
+    io::{BufWriter, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+};
+use tokio_io::{io as async_io, AsyncWrite};
+
+/// An error when downloading a file
+#[derive(Debug, failure::Fail)]
+pub enum DownloadError {
+    /// File is larger than allowed
+    #[fail(display = "File is larger than {} bytes", max_size)]
+    TooLarge {
+        /// Maximum allowed size
+        max_size: u64,
+    },
+    /// Number of received bytes is not equal to expected file size
+    #[fail(display = "Expected {} bytes, received {} bytes", expected, received)]
+    SizeMismatch {
+        /// Expected size
+        expected: u64,
+        /// Received size
+        received: u64,
+    },
+}
+
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+type ProgressCallback = Box<dyn FnMut(u64, Option<u64>) + Send>;
+
+/// A stream of file chunks
+///
+/// See [Api::download_file_stream()](struct.Api.html#method.download_file_stream)
+#[must_use = "streams do nothing unless polled"]
+pub struct DownloadStream {
+    inner: Box<dyn Stream<Item = Vec<u8>, Error = Error> + Send>,
+    max_size: Option<u64>,
+    expected_size: Option<u64>,
+    progress: Option<ProgressCallback>,
+    received: u64,
+    started: bool,
+}
+
+impl DownloadStream {
+    pub(super) fn new(inner: Box<dyn Stream<Item = Vec<u8>, Error = Error> + Send>) -> Self {
+        DownloadStream {
+            inner,
+            max_size: None,
+            expected_size: None,
+            progress: None,
+            received: 0,
+            started: false,
+        }
+    }
+
+    /// Reads a file from disk in a blocking section, see [Api::download_file()](struct.Api.html#method.download_file)
+    pub(super) fn from_path(path: PathBuf) -> Self {
+        let mut file = None;
+        DownloadStream::new(Box::new(stream::poll_fn(move || {
+            poll_blocking(|| {
+                let file = match file {
+                    Some(ref mut file) => file,
+                    None => file.get_or_insert(File::open(&path)?),
+                };
+                let mut buf = vec![0; READ_CHUNK_SIZE];
+                let len = file.read(&mut buf)?;
+                Ok(if len == 0 {
+                    None
+                } else {
+                    buf.truncate(len);
+                    Some(buf)
+                })
+            })
+        })))
+    }
+
+    /// Aborts download with [DownloadError::TooLarge](enum.DownloadError.html) when file is larger than a given size
+    pub fn max_size(mut self, max_size: u64) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    /// Sets expected size of a file
+    ///
+    /// Use `file_size` of a [File](types/struct.File.html) returned by `GetFile` method.
+    /// Download fails with [DownloadError::SizeMismatch](enum.DownloadError.html)
+    /// when number of received bytes differs.
+    pub fn expected_size(mut self, expected_size: u64) -> Self {
+        self.expected_size = Some(expected_size);
+        self
+    }
+
+    /// Sets a callback called after each received chunk
+    ///
+    /// Callback receives number of bytes received so far and expected size of a file, if set
+    pub fn progress<F>(mut self, progress: F) -> Self
+    where
+        F: FnMut(u64, Option<u64>) + Send + 'static,
+    {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// Writes a file to a given writer
+    ///
+    /// Returns the writer and number of written bytes
+    pub fn write_to<W>(self, writer: W) -> ApiFuture<(W, u64)>
+    where
+        W: AsyncWrite + Send + 'static,
+    {
+        ApiFuture {
+            inner: Box::new(
+                self.fold((writer, 0), |(writer, written), chunk| {
+                    let len = chunk.len() as u64;
+                    async_io::write_all(writer, chunk).map(move |(writer, _)| (writer, written + len))
+                })
+                .and_then(|(writer, written)| async_io::flush(writer).from_err().map(move |writer| (writer, written))),
+            ),
+        }
+    }
+
+    /// Saves a file to a given path
+    ///
+    /// File is written to a temporary `<path>.part` file which is renamed when download succeeds,
+    /// so an existing file is replaced only by a complete download.
+    /// Writes do not block the reactor when running on a thread pool.
+    /// Returns number of written bytes.
+    pub fn save<P: AsRef<Path>>(self, path: P) -> ApiFuture<u64> {
+        let path = path.as_ref().to_path_buf();
+        let mut tmp_path = path.clone().into_os_string();
+        tmp_path.push(".part");
+        let tmp_path = PathBuf::from(tmp_path);
+        let write_path = tmp_path.clone();
+        let remove_path = tmp_path.clone();
+        ApiFuture {
+            inner: Box::new(
+                self.fold((None, 0), move |(file, written), chunk| {
+                    let path = write_path.clone();
+                    // file is created on first chunk
+                    let mut file: Option<Option<BufWriter<File>>> = Some(file);
+                    blocking(move || {
+                        let mut file = match file.take().expect("Chunk is written already") {
+                            Some(file) => file,
+                            None => BufWriter::new(File::create(&path)?),
+                        };
+                        file.write_all(&chunk)?;
+                        Ok((Some(file), written + chunk.len() as u64))
+                    })
+                })
+                .and_then(move |(file, written)| {
+                    let mut file = Some(file);
+                    blocking(move || {
+                        match file.take().expect("File is saved already") {
+                            Some(mut file) => {
+                                file.flush()?;
+                                file.get_ref().sync_all()?;
+                            }
+                            None => {
+                                File::create(&tmp_path)?;
+                            }
+                        }
+                        rename(&tmp_path, &path)?;
+                        Ok(written)
+                    })
+                })
+                .or_else(move |err| {
+                    if let Err(remove_err) = remove_file(&remove_path) {
+                        if remove_err.kind() != ErrorKind::NotFound {
+                            log::warn!("Failed to remove {}: {}", remove_path.display(), remove_err);
+                        }
+                    }
+                    Err(err)
+                }),
+            ),
+        }
+    }
+}
+
+impl Stream for DownloadStream {
+    type Item = Vec<u8>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        if !self.started {
+            self.started = true;
+            if let (Some(max_size), Some(expected_size)) = (self.max_size, self.expected_size) {
+                if expected_size > max_size {
+                    return Err(DownloadError::TooLarge { max_size }.into());
+                }
+            }
+        }
+        match try_ready!(self.inner.poll()) {
+            Some(chunk) => {
+                self.received += chunk.len() as u64;
+                if let Some(max_size) = self.max_size {
+                    if self.received > max_size {
+                        return Err(DownloadError::TooLarge { max_size }.into());
+                    }
+                }
+                if let Some(ref mut progress) = self.progress {
+                    progress(self.received, self.expected_size);
+                }
+                Ok(Async::Ready(Some(chunk)))
+            }
+            None => {
+                if let Some(expected) = self.expected_size {
+                    if expected != self.received {
+                        return Err(DownloadError::SizeMismatch {
+                            expected,
+                            received: self.received,
+                        }
+                        .into());
+                    }
+                }
+                Ok(Async::Ready(None))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        env, fs,
+        io::Cursor,
+        sync::{Arc, Mutex},
+    };
+
+    fn create_stream() -> DownloadStream {
+        DownloadStream::new(Box::new(stream::iter_ok(vec![b"abc".to_vec(), b"de".to_vec()])))
+    }
+
+    #[test]
+    fn progress() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let progress_calls = calls.clone();
+        let chunks = create_stream()
+            .expected_size(5)
+            .max_size(5)
+            .progress(move |received, total| progress_calls.lock().unwrap().push((received, total)))
+            .collect()
+            .wait()
+            .unwrap();
+        assert_eq!(chunks, vec![b"abc".to_vec(), b"de".to_vec()]);
+        assert_eq!(*calls.lock().unwrap(), vec![(3, Some(5)), (5, Some(5))]);
+    }
+
+    #[test]
+    fn too_large() {
+        for stream in [
+            create_stream().max_size(4),
+            create_stream().expected_size(5).max_size(1),
+        ] {
+            match stream
+                .collect()
+                .wait()
+                .unwrap_err()
+                .downcast::<DownloadError>()
+                .unwrap()
+            {
+                DownloadError::TooLarge { max_size } => assert!(max_size < 5),
+                err => panic!("Unexpected error: {:?}", err),
+            }
+        }
+    }
+
+    #[test]
+    fn size_mismatch() {
+        let err = create_stream().expected_size(6).collect().wait().unwrap_err();
+        match err.downcast::<DownloadError>().unwrap() {
+            DownloadError::SizeMismatch { expected, received } => {
+                assert_eq!(expected, 6);
+                assert_eq!(received, 5);
+            }
+            err => panic!("Unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn write_to() {
+        let (writer, written) = create_stream().write_to(Cursor::new(Vec::new())).wait().unwrap();
+        assert_eq!(written, 5);
+        assert_eq!(writer.into_inner(), b"abcde");
+    }
+
+    #[test]
+    fn from_path() {
+        let path = env::temp_dir().join(format!("tgbot-local-file-{}", std::process::id()));
+        let data = vec![1; READ_CHUNK_SIZE + 1];
+        fs::write(&path, &data).unwrap();
+        let chunks = DownloadStream::from_path(path.clone())
+            .expected_size(data.len() as u64)
+            .collect()
+            .wait()
+            .unwrap();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks.concat(), data);
+        fs::remove_file(&path).unwrap();
+        assert!(DownloadStream::from_path(path).collect().wait().is_err());
+    }
+
+    #[test]
+    fn save() {
+        let path = env::temp_dir().join(format!("tgbot-download-{}", std::process::id()));
+        assert_eq!(create_stream().save(&path).wait().unwrap(), 5);
+        assert_eq!(fs::read(&path).unwrap(), b"abcde");
+        let pool = tokio_threadpool::ThreadPool::new();
+        let stream = DownloadStream::new(Box::new(stream::iter_ok(vec![b"fgh".to_vec()])));
+        assert_eq!(pool.spawn_handle(stream.save(&path)).wait().unwrap(), 3);
+        assert_eq!(fs::read(&path).unwrap(), b"fgh");
+        let empty = DownloadStream::new(Box::new(stream::empty()));
+        assert_eq!(empty.save(&path).wait().unwrap(), 0);
+        assert_eq!(fs::read(&path).unwrap(), b"");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn save_failed() {
+        let path = env::temp_dir().join(format!("tgbot-download-failed-{}", std::process::id()));
+        let part_path = env::temp_dir().join(format!("tgbot-download-failed-{}.part", std::process::id()));
+        fs::write(&path, b"existing").unwrap();
+        // fails after the first chunk is written
+        assert!(create_stream().max_size(4).save(&path).wait().is_err());
+        let failing = DownloadStream::new(Box::new(stream::iter_result(vec![
+            Ok(b"abc".to_vec()),
+            Err(failure::err_msg("not found")),
+        ])));
+        assert!(failing.save(&path).wait().is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"existing");
+        assert!(!part_path.exists());
+        fs::remove_file(&path).unwrap();
+
+        // file is not created when download fails
+        assert!(create_stream().max_size(1).save(&path).wait().is_err());
+        assert!(!path.exists());
+    }
+}
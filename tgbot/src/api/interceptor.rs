@@ -0,0 +1,151 @@
+use crate::{executor::Executor, request::Request};
+use failure::Error;
+use futures::{Async, Future, Stream};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// Intercepts requests sent by [Api](struct.Api.html)
+///
+/// Use it for logging, metrics, auditing, etc.
+/// Interceptors are called for each HTTP request, so a retried method is seen several times.
+pub trait Interceptor: Send + Sync {
+    /// Called before a request is sent
+    ///
+    /// Note that URL of a request contains bot token.
+    fn before_request(&self, _info: &RequestInfo, _request: &Request) {}
+
+    /// Called when a response is received
+    ///
+    /// # Arguments
+    ///
+    /// * info - Information about a request
+    /// * response - Raw response body or an error if request has failed (body is empty for streaming downloads)
+    /// * duration - Time spent on request (including time spent waiting for send limits)
+    fn after_response(&self, _info: &RequestInfo, _response: Result<&[u8], &Error>, _duration: Duration) {}
+}
+
+/// Information about an intercepted request
+#[derive(Clone, Debug)]
+pub struct RequestInfo {
+    is_download: bool,
+    path: String,
+}
+
+impl RequestInfo {
+    pub(super) fn method(path: &str) -> Self {
+        RequestInfo {
+            is_download: false,
+            path: String::from(path),
+        }
+    }
+
+    pub(super) fn download(path: &str) -> Self {
+        RequestInfo {
+            is_download: true,
+            path: String::from(path),
+        }
+    }
+
+    /// Returns a name of a Bot API method (e.g. `sendMessage`)
+    ///
+    /// Returns `None` when a file is downloading
+    pub fn get_method_name(&self) -> Option<&str> {
+        if self.is_download {
+            None
+        } else {
+            Some(&self.path)
+        }
+    }
+
+    /// Returns a path of a request without host and token (e.g. `sendMessage`)
+    pub fn get_path(&self) -> &str {
+        &self.path
+    }
+}
+
+pub(super) struct InterceptedExecutor {
+    executor: Arc<Box<dyn Executor>>,
+    interceptors: Arc<Vec<Arc<dyn Interceptor>>>,
+    info: Arc<RequestInfo>,
+}
+
+impl InterceptedExecutor {
+    pub(super) fn new(
+        executor: Arc<Box<dyn Executor>>,
+        interceptors: Arc<Vec<Arc<dyn Interceptor>>>,
+        info: RequestInfo,
+    ) -> Self {
+        InterceptedExecutor {
+            executor,
+            interceptors,
+            info: Arc::new(info),
+        }
+    }
+}
+
+impl Executor for InterceptedExecutor {
+    fn execute(&self, req: Request) -> Box<dyn Future<Item = Vec<u8>, Error = Error> + Send> {
+        for interceptor in self.interceptors.iter() {
+            interceptor.before_request(&self.info, &req);
+        }
+        let started_at = Instant::now();
+        let interceptors = self.interceptors.clone();
+        let info = self.info.clone();
+        Box::new(self.executor.execute(req).then(move |result| {
+            let duration = started_at.elapsed();
+            for interceptor in interceptors.iter() {
+                interceptor.after_response(&info, result.as_ref().map(|data| data.as_slice()), duration);
+            }
+            result
+        }))
+    }
+
+    fn execute_stream(&self, req: Request) -> Box<dyn Stream<Item = Vec<u8>, Error = Error> + Send> {
+        for interceptor in self.interceptors.iter() {
+            interceptor.before_request(&self.info, &req);
+        }
+        let started_at = Instant::now();
+        let interceptors = self.interceptors.clone();
+        let info = self.info.clone();
+        let mut stream = self.executor.execute_stream(req);
+        Box::new(futures::stream::poll_fn(move || {
+            let result = stream.poll();
+            let response = match result {
+                Ok(Async::Ready(None)) => Some(Ok(&[][..])),
+                Err(ref err) => Some(Err(err)),
+                _ => None,
+            };
+            if let Some(response) = response {
+                let duration = started_at.elapsed();
+                for interceptor in interceptors.iter() {
+                    interceptor.after_response(&info, response, duration);
+                }
+            }
+            result
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::methods::{Method, RawMethod};
+    use serde_json::json;
+
+    #[test]
+    fn request_info() {
+        let info = RequestInfo::method("sendMessage");
+        assert_eq!(info.get_method_name(), Some("sendMessage"));
+        assert_eq!(info.get_path(), "sendMessage");
+
+        let builder = RawMethod::new("getChat", json!({"chat_id": 1})).into_request().unwrap();
+        let info = RequestInfo::method(builder.get_path());
+        assert_eq!(info.get_method_name(), Some("getChat"));
+
+        let info = RequestInfo::download("file-path");
+        assert!(info.get_method_name().is_none());
+        assert_eq!(info.get_path(), "file-path");
+    }
+}
@@ -0,0 +1,297 @@
+use crate::{
+    executor::Executor,
+    request::Request,
+    types::{Response, ResponseError},
+};
+use failure::Error;
+use futures::{
+    future::{self, loop_fn, Either, Loop},
+    Future,
+};
+use serde::de::DeserializeOwned;
+use std::{sync::Arc, time::Duration};
+use tokio_timer::sleep;
+
+const DEFAULT_MAX_ATTEMPTS: usize = 3;
+
+/// Defines how to retry a request when Telegram asks to do so
+///
+/// A request is retried when response contains
+/// `retry_after` (flood control) or `migrate_to_chat_id` (group has been migrated to a supergroup) parameter.
+/// Requests with file readers can not be retried.
+/// When all attempts fail, the last [ResponseError](types/struct.ResponseError.html) is returned,
+/// its `attempts` field contains a number of attempts made.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    max_attempts: usize,
+    max_retry_after: Option<Duration>,
+    follow_migrations: bool,
+}
+
+impl RetryPolicy {
+    /// Creates a new policy with default values
+    pub fn new() -> Self {
+        RetryPolicy {
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            max_retry_after: None,
+            follow_migrations: true,
+        }
+    }
+
+    /// Maximum number of attempts including the first one
+    ///
+    /// Defaults to 3
+    pub fn max_attempts(mut self, max_attempts: usize) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Do not retry when Telegram asks to wait longer than given duration
+    ///
+    /// Not limited by default
+    pub fn max_retry_after(mut self, max_retry_after: Duration) -> Self {
+        self.max_retry_after = Some(max_retry_after);
+        self
+    }
+
+    /// Whether to retry a request with a new chat_id
+    /// when a group has been migrated to a supergroup
+    ///
+    /// Defaults to `true`
+    pub fn follow_migrations(mut self, follow_migrations: bool) -> Self {
+        self.follow_migrations = follow_migrations;
+        self
+    }
+
+    /// Returns maximum number of attempts
+    pub fn get_max_attempts(&self) -> usize {
+        self.max_attempts
+    }
+
+    /// Returns maximum time to wait before next attempt
+    pub fn get_max_retry_after(&self) -> Option<Duration> {
+        self.max_retry_after
+    }
+
+    /// Returns whether migrations are followed
+    pub fn get_follow_migrations(&self) -> bool {
+        self.follow_migrations
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::new()
+    }
+}
+
+pub(super) fn parse_response<T: DeserializeOwned>(data: &[u8]) -> Result<T, Error> {
+    match serde_json::from_slice::<Response<T>>(data)? {
+        Response::Success(obj) => Ok(obj),
+        Response::Error(err) => Err(err.into()),
+    }
+}
+
+pub(super) fn execute_with_retries<T>(
+    executor: Arc<Box<dyn Executor>>,
+    request: Request,
+    policy: RetryPolicy,
+) -> impl Future<Item = T, Error = Error>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    loop_fn((request, 1), move |(request, attempt)| {
+        let next_request = if attempt < policy.max_attempts {
+            request.try_clone()
+        } else {
+            None
+        };
+        let policy = policy.clone();
+        executor
+            .execute(request)
+            .and_then(|data| parse_response::<T>(&data))
+            .then(move |result| match result {
+                Ok(value) => Either::A(future::ok(Loop::Break(value))),
+                Err(err) => Either::B(handle_error(err, next_request, attempt, &policy)),
+            })
+    })
+}
+
+type RetryFuture<T> = Box<dyn Future<Item = Loop<T, (Request, usize)>, Error = Error> + Send>;
+
+fn handle_error<T>(err: Error, next_request: Option<Request>, attempt: usize, policy: &RetryPolicy) -> RetryFuture<T>
+where
+    T: Send + 'static,
+{
+    let err = match err.downcast::<ResponseError>() {
+        Ok(err) => err,
+        Err(err) => return Box::new(future::err(err)),
+    };
+    // error type is kept, so callers can downcast it regardless of retries
+    let fail = move |mut err: ResponseError| -> RetryFuture<T> {
+        if attempt > 1 {
+            log::warn!("Request has failed after {} attempts: {}", attempt, err);
+        }
+        err.attempts = Some(attempt);
+        Box::new(future::err(err.into()))
+    };
+    let (mut request, parameters) = match (next_request, err.parameters) {
+        (Some(request), Some(parameters)) => (request, parameters),
+        _ => return fail(err),
+    };
+    if let Some(retry_after) = parameters.retry_after {
+        let retry_after = Duration::from_secs(retry_after as u64);
+        if policy.max_retry_after.map(|max| retry_after > max).unwrap_or(false) {
+            return fail(err);
+        }
+        log::info!(
+            "Flood control exceeded, retrying in {:?} (attempt {} of {})",
+            retry_after,
+            attempt + 1,
+            policy.max_attempts
+        );
+        return Box::new(
+            sleep(retry_after)
+                .from_err()
+                .map(move |()| Loop::Continue((request, attempt + 1))),
+        );
+    }
+    if let (Some(chat_id), true) = (parameters.migrate_to_chat_id, policy.follow_migrations) {
+        match request.replace_chat_id(chat_id) {
+            Ok(true) => {
+                log::info!(
+                    "Group has been migrated to a supergroup, retrying with chat_id={}",
+                    chat_id
+                );
+                return Box::new(future::ok(Loop::Continue((request, attempt + 1))));
+            }
+            Ok(false) => {}
+            Err(replace_err) => log::warn!("Failed to replace chat_id: {}", replace_err),
+        }
+    }
+    fail(err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        request::{RequestBody, RequestBuilder},
+        types::ErrorKind,
+    };
+    use serde_json::{json, Value};
+    use std::sync::Mutex;
+    use tokio::runtime::current_thread::block_on_all;
+
+    struct ExecutorMock {
+        requests: Mutex<Vec<Value>>,
+        responses: Mutex<Vec<Value>>,
+    }
+
+    impl ExecutorMock {
+        fn new(mut responses: Vec<Value>) -> Arc<Self> {
+            responses.reverse();
+            Arc::new(ExecutorMock {
+                requests: Mutex::new(Vec::new()),
+                responses: Mutex::new(responses),
+            })
+        }
+    }
+
+    impl Executor for Arc<ExecutorMock> {
+        fn execute(&self, req: Request) -> Box<dyn Future<Item = Vec<u8>, Error = Error> + Send> {
+            match req.body {
+                RequestBody::Json(data) => self
+                    .requests
+                    .lock()
+                    .unwrap()
+                    .push(serde_json::from_slice(&data).unwrap()),
+                body => panic!("Unexpected request body: {:?}", body),
+            }
+            let rep = self.responses.lock().unwrap().pop().expect("No more responses");
+            Box::new(future::ok(serde_json::to_vec(&rep).unwrap()))
+        }
+    }
+
+    fn execute(executor: &Arc<ExecutorMock>, policy: RetryPolicy) -> Result<bool, Error> {
+        let request = RequestBuilder::json("method", &json!({"chat_id": 1}))
+            .unwrap()
+            .build("host", "token");
+        block_on_all(execute_with_retries(
+            Arc::new(Box::new(executor.clone())),
+            request,
+            policy,
+        ))
+    }
+
+    fn too_many_requests(retry_after: u64) -> Value {
+        json!({
+            "ok": false,
+            "error_code": 429,
+            "description": format!("Too Many Requests: retry after {}", retry_after),
+            "parameters": {"retry_after": retry_after}
+        })
+    }
+
+    fn migrated() -> Value {
+        json!({
+            "ok": false,
+            "error_code": 400,
+            "description": "Bad Request: group chat was upgraded to a supergroup chat",
+            "parameters": {"migrate_to_chat_id": 2}
+        })
+    }
+
+    #[test]
+    fn retry_after() {
+        let executor = ExecutorMock::new(vec![too_many_requests(0), json!({"ok": true, "result": true})]);
+        assert!(execute(&executor, RetryPolicy::new()).unwrap());
+        assert_eq!(executor.requests.lock().unwrap().len(), 2);
+
+        let executor = ExecutorMock::new(vec![too_many_requests(0), too_many_requests(0)]);
+        let err = execute(&executor, RetryPolicy::new().max_attempts(2)).unwrap_err();
+        assert_eq!(executor.requests.lock().unwrap().len(), 2);
+        let err = err.downcast_ref::<ResponseError>().unwrap();
+        assert_eq!(err.kind(), ErrorKind::TooManyRequests);
+        assert_eq!(err.parameters.and_then(|parameters| parameters.retry_after), Some(0));
+        assert_eq!(err.attempts, Some(2));
+
+        let executor = ExecutorMock::new(vec![too_many_requests(10)]);
+        let policy = RetryPolicy::new().max_retry_after(Duration::from_secs(5));
+        let err = execute(&executor, policy).unwrap_err();
+        assert_eq!(err.downcast::<ResponseError>().unwrap().attempts, Some(1));
+    }
+
+    #[test]
+    fn migrate_to_chat_id() {
+        let executor = ExecutorMock::new(vec![migrated(), json!({"ok": true, "result": true})]);
+        assert!(execute(&executor, RetryPolicy::new()).unwrap());
+        assert_eq!(
+            *executor.requests.lock().unwrap(),
+            vec![json!({"chat_id": 1}), json!({"chat_id": 2})]
+        );
+
+        let executor = ExecutorMock::new(vec![migrated()]);
+        let err = execute(&executor, RetryPolicy::new().follow_migrations(false)).unwrap_err();
+        assert!(err.downcast::<ResponseError>().is_ok());
+    }
+
+    #[test]
+    fn not_retryable() {
+        let executor = ExecutorMock::new(vec![json!({
+            "ok": false,
+            "error_code": 400,
+            "description": "Bad Request: chat not found"
+        })]);
+        let err = execute(&executor, RetryPolicy::new()).unwrap_err();
+        assert_eq!(err.downcast::<ResponseError>().unwrap().error_code, Some(400));
+    }
+
+    #[test]
+    fn policy() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.get_max_attempts(), DEFAULT_MAX_ATTEMPTS);
+        assert!(policy.get_max_retry_after().is_none());
+        assert!(policy.get_follow_migrations());
+    }
+}
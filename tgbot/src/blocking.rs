@@ -0,0 +1,26 @@
+use failure::Error;
+use futures::{future, Async, Future, Poll};
+use std::io;
+
+/// Runs a blocking file operation without blocking the reactor
+///
+/// Operation is run on the current thread when it is not a thread pool worker,
+/// e.g. when current_thread runtime is used
+pub(crate) fn poll_blocking<F, T>(mut f: F) -> Poll<T, Error>
+where
+    F: FnMut() -> io::Result<T>,
+{
+    match tokio_threadpool::blocking(&mut f) {
+        Ok(Async::Ready(result)) => result.map(Async::Ready).map_err(Error::from),
+        Ok(Async::NotReady) => Ok(Async::NotReady),
+        Err(_) => f().map(Async::Ready).map_err(Error::from),
+    }
+}
+
+/// Returns a future running a blocking file operation, see [poll_blocking()](fn.poll_blocking.html)
+pub(crate) fn blocking<F, T>(mut f: F) -> impl Future<Item = T, Error = Error>
+where
+    F: FnMut() -> io::Result<T>,
+{
+    future::poll_fn(move || poll_blocking(&mut f))
+}
@@ -0,0 +1,451 @@
+use crate::{
+    executor::Executor,
+    request::{Form, FormValue, Request, RequestBody, RequestMethod},
+    types::InputFileKind,
+};
+use failure::Error;
+use futures::{future, Future};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map as JsonMap, Value as JsonValue};
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+use url::Url;
+
+const TOKEN_PLACEHOLDER: &str = "<token>";
+
+/// An error when replaying a cassette
+#[derive(Debug, failure::Fail)]
+pub enum CassetteError {
+    /// All recorded interactions have been replayed
+    #[fail(display = "Cassette has no more interactions for request: {}", _0)]
+    Exhausted(String),
+    /// A request does not match a recorded one
+    #[fail(display = "Request does not match a cassette: expected {}, got {}", expected, actual)]
+    Mismatch {
+        /// Recorded request
+        expected: String,
+        /// Actual request
+        actual: String,
+    },
+    /// A recorded request has failed
+    #[fail(display = "Recorded request has failed: {}", _0)]
+    Recorded(String),
+}
+
+/// Normalized request, does not contain a host, token and multipart boundaries
+///
+/// Token is replaced in URL path and in body values, e.g. in a webhook URL
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+struct RecordedRequest {
+    method: String,
+    url: String,
+    body: JsonValue,
+}
+
+impl RecordedRequest {
+    fn new(req: &Request) -> Result<Self, Error> {
+        let (url, token) = normalize_url(&req.url)?;
+        let mut body = match req.body {
+            RequestBody::Form(ref form) => normalize_form(form),
+            RequestBody::Json(ref data) => serde_json::from_slice(data)?,
+            RequestBody::Empty => JsonValue::Null,
+        };
+        if let Some(token) = token {
+            redact_token(&mut body, &token);
+        }
+        Ok(RecordedRequest {
+            method: String::from(match req.method {
+                RequestMethod::Get => "GET",
+                RequestMethod::Post => "POST",
+            }),
+            url,
+            body,
+        })
+    }
+
+    fn describe(&self) -> String {
+        serde_json::to_string(self).unwrap_or_else(|_| format!("{:?}", self))
+    }
+}
+
+/// Returns a URL path without a token and the token
+fn normalize_url(url: &str) -> Result<(String, Option<String>), Error> {
+    let url = Url::parse(url)?;
+    let mut token = None;
+    let segments: Vec<String> = url
+        .path_segments()
+        .map(|segments| {
+            segments
+                .map(|segment| match segment.get(3..) {
+                    Some(value) if token.is_none() && segment.starts_with("bot") => {
+                        token = Some(String::from(value));
+                        format!("bot{}", TOKEN_PLACEHOLDER)
+                    }
+                    _ => String::from(segment),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    Ok((
+        format!("/{}", segments.join("/")),
+        token.filter(|token| !token.is_empty()),
+    ))
+}
+
+/// Replaces a token in all strings of a body
+fn redact_token(value: &mut JsonValue, token: &str) {
+    match value {
+        JsonValue::String(text) if text.contains(token) => *text = text.replace(token, TOKEN_PLACEHOLDER),
+        JsonValue::Array(items) => {
+            for item in items {
+                redact_token(item, token);
+            }
+        }
+        JsonValue::Object(fields) => {
+            for field in fields.values_mut() {
+                redact_token(field, token);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn normalize_form(form: &Form) -> JsonValue {
+    let mut fields = JsonMap::new();
+    for (name, value) in &form.fields {
+        let value = match value {
+            FormValue::Text(text) => JsonValue::from(text.as_str()),
+            FormValue::File(file) => match file.get_kind() {
+                InputFileKind::Id(file_id) => serde_json::json!({ "file_id": file_id }),
+                InputFileKind::Url(url) => serde_json::json!({ "url": url }),
+                InputFileKind::Path(path) => serde_json::json!({ "path": path.to_string_lossy() }),
+                InputFileKind::LocalPath(path) => serde_json::json!({ "local_path": path.to_string_lossy() }),
+                InputFileKind::Reader(reader) => {
+                    serde_json::json!({ "reader": reader.get_info().map(|info| info.get_name()) })
+                }
+                InputFileKind::Stream(stream) => {
+                    serde_json::json!({ "stream": stream.get_info().map(|info| info.get_name()) })
+                }
+            },
+        };
+        fields.insert(name.clone(), value);
+    }
+    JsonValue::Object(fields)
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum RecordedResponse {
+    Text(String),
+    Bytes(Vec<u8>),
+    Error(String),
+}
+
+impl RecordedResponse {
+    fn new(result: &Result<Vec<u8>, Error>) -> Self {
+        match result {
+            Ok(data) => match String::from_utf8(data.clone()) {
+                Ok(text) => RecordedResponse::Text(text),
+                Err(_) => RecordedResponse::Bytes(data.clone()),
+            },
+            Err(err) => RecordedResponse::Error(err.to_string()),
+        }
+    }
+
+    fn into_result(self) -> Result<Vec<u8>, Error> {
+        match self {
+            RecordedResponse::Text(text) => Ok(text.into_bytes()),
+            RecordedResponse::Bytes(data) => Ok(data),
+            RecordedResponse::Error(err) => Err(CassetteError::Recorded(err).into()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct Interaction {
+    request: RecordedRequest,
+    response: RecordedResponse,
+}
+
+/// Writes every request and response to a cassette file
+///
+/// A cassette is a JSONL file where each line contains a request and a response.
+/// Requests are normalized: host and bot token are removed from URL
+/// and multipart/form-data is stored as a JSON object of fields, so file readers are not read.
+///
+/// Use [ReplayExecutor](struct.ReplayExecutor.html) in order to replay a cassette.
+pub struct RecordingExecutor {
+    executor: Box<dyn Executor>,
+    file: Arc<Mutex<File>>,
+}
+
+impl RecordingExecutor {
+    /// Creates a new recording executor
+    ///
+    /// # Arguments
+    ///
+    /// * executor - Executor to send requests
+    /// * path - Path to a cassette file, existing file will be truncated
+    pub fn new<E, P>(executor: E, path: P) -> Result<Self, Error>
+    where
+        E: Executor + 'static,
+        P: AsRef<Path>,
+    {
+        RecordingExecutor::boxed(Box::new(executor), path)
+    }
+
+    pub(crate) fn boxed<P: AsRef<Path>>(executor: Box<dyn Executor>, path: P) -> Result<Self, Error> {
+        Ok(RecordingExecutor {
+            executor,
+            file: Arc::new(Mutex::new(File::create(path)?)),
+        })
+    }
+}
+
+impl Executor for RecordingExecutor {
+    fn execute(&self, req: Request) -> Box<dyn Future<Item = Vec<u8>, Error = Error> + Send> {
+        let request = match RecordedRequest::new(&req) {
+            Ok(request) => request,
+            Err(err) => return Box::new(future::err(err)),
+        };
+        let file = self.file.clone();
+        Box::new(self.executor.execute(req).then(move |result| {
+            let interaction = Interaction {
+                request,
+                response: RecordedResponse::new(&result),
+            };
+            let mut line = serde_json::to_vec(&interaction)?;
+            line.push(b'\n');
+            let mut file = file.lock().expect("Cassette file is poisoned");
+            file.write_all(&line)?;
+            file.flush()?;
+            result
+        }))
+    }
+}
+
+/// Serves responses from a cassette file
+///
+/// Each request is matched against the first unused interaction with an equal request,
+/// so concurrent requests may be replayed in a different order than recorded.
+/// [CassetteError::Mismatch](enum.CassetteError.html) is returned when there is no such interaction.
+pub struct ReplayExecutor {
+    interactions: Mutex<VecDeque<Interaction>>,
+}
+
+impl ReplayExecutor {
+    /// Loads a cassette from a file
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let mut interactions = VecDeque::new();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                interactions.push_back(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(ReplayExecutor {
+            interactions: Mutex::new(interactions),
+        })
+    }
+
+    /// Returns number of interactions not replayed yet
+    pub fn remaining(&self) -> usize {
+        self.interactions.lock().expect("Cassette is poisoned").len()
+    }
+}
+
+impl Executor for ReplayExecutor {
+    fn execute(&self, req: Request) -> Box<dyn Future<Item = Vec<u8>, Error = Error> + Send> {
+        let actual = match RecordedRequest::new(&req) {
+            Ok(request) => request,
+            Err(err) => return Box::new(future::err(err)),
+        };
+        let mut interactions = self.interactions.lock().expect("Cassette is poisoned");
+        let position = interactions
+            .iter()
+            .position(|interaction| interaction.request == actual);
+        Box::new(future::result(match (position, interactions.front()) {
+            (Some(position), _) => interactions
+                .remove(position)
+                .expect("Interaction position is out of range")
+                .response
+                .into_result(),
+            (None, Some(expected)) => Err(CassetteError::Mismatch {
+                expected: expected.request.describe(),
+                actual: actual.describe(),
+            }
+            .into()),
+            (None, None) => Err(CassetteError::Exhausted(actual.describe()).into()),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{request::RequestBuilder, types::InputFile};
+    use futures::{task, Async};
+    use std::{env, fs, io::Cursor, path::PathBuf};
+
+    struct ExecutorMock;
+
+    impl Executor for ExecutorMock {
+        fn execute(&self, req: Request) -> Box<dyn Future<Item = Vec<u8>, Error = Error> + Send> {
+            if req.url.ends_with("slow") {
+                // completes after a request sent later
+                let mut is_polled = false;
+                return Box::new(future::poll_fn(move || {
+                    if is_polled {
+                        Ok(Async::Ready(b"slow".to_vec()))
+                    } else {
+                        is_polled = true;
+                        task::current().notify();
+                        Ok(Async::NotReady)
+                    }
+                }));
+            }
+            Box::new(if req.url.ends_with("fail") {
+                future::err(failure::err_msg("failed"))
+            } else if req.url.ends_with("binary") {
+                future::ok(vec![0, 159, 146, 150])
+            } else {
+                future::ok(br#"{"ok":true,"result":true}"#.to_vec())
+            })
+        }
+    }
+
+    fn cassette_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("tgbot-cassette-{}-{}.jsonl", name, std::process::id()))
+    }
+
+    fn json_request(token: &str) -> Request {
+        RequestBuilder::json("sendMessage", &serde_json::json!({"chat_id": 1, "text": "test"}))
+            .unwrap()
+            .build("https://api.telegram.org", token)
+    }
+
+    fn form_request() -> Request {
+        let mut form = Form::new();
+        form.insert_field("chat_id", 1);
+        form.insert_field("document", InputFile::from(Cursor::new(b"data")));
+        RequestBuilder::form("sendDocument", form)
+            .unwrap()
+            .build("https://api.telegram.org", "123:token")
+    }
+
+    #[test]
+    fn normalize() {
+        assert_eq!(
+            normalize_url("https://api.telegram.org/bot123:token/getMe").unwrap(),
+            (String::from("/bot<token>/getMe"), Some(String::from("123:token")))
+        );
+        assert_eq!(
+            normalize_url("http://127.0.0.1:8081/file/bot123:token/documents/bot.txt").unwrap(),
+            (
+                String::from("/file/bot<token>/documents/bot.txt"),
+                Some(String::from("123:token"))
+            )
+        );
+        assert_eq!(
+            normalize_url("https://api.telegram.org/bot/getMe").unwrap(),
+            (String::from("/bot<token>/getMe"), None)
+        );
+        let req = RecordedRequest::new(&form_request()).unwrap();
+        assert_eq!(req.method, "POST");
+        assert_eq!(req.url, "/bot<token>/sendDocument");
+        assert_eq!(
+            req.body,
+            serde_json::json!({"chat_id": "1", "document": {"reader": null}})
+        );
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let path = cassette_path("record");
+        let recorder = RecordingExecutor::new(ExecutorMock, &path).unwrap();
+        recorder.execute(json_request("123:token")).wait().unwrap();
+        recorder.execute(form_request()).wait().unwrap();
+        let req = RequestBuilder::empty("binary").unwrap().build("host://", "123:token");
+        recorder.execute(req).wait().unwrap();
+        let req = RequestBuilder::empty("fail").unwrap().build("host://", "123:token");
+        assert!(recorder.execute(req).wait().is_err());
+
+        let replay = ReplayExecutor::new(&path).unwrap();
+        assert_eq!(replay.remaining(), 4);
+        let data = replay.execute(json_request("456:other")).wait().unwrap();
+        assert_eq!(data, br#"{"ok":true,"result":true}"#);
+        replay.execute(form_request()).wait().unwrap();
+        let req = RequestBuilder::empty("binary").unwrap().build("host://", "456:other");
+        assert_eq!(replay.execute(req).wait().unwrap(), vec![0, 159, 146, 150]);
+        let req = RequestBuilder::empty("fail").unwrap().build("host://", "456:other");
+        let err = replay.execute(req).wait().unwrap_err();
+        match err.downcast::<CassetteError>().unwrap() {
+            CassetteError::Recorded(msg) => assert_eq!(msg, "failed"),
+            err => panic!("Unexpected error: {:?}", err),
+        }
+        assert_eq!(replay.remaining(), 0);
+        let err = replay.execute(json_request("123:token")).wait().unwrap_err();
+        match err.downcast::<CassetteError>().unwrap() {
+            CassetteError::Exhausted(_) => {}
+            err => panic!("Unexpected error: {:?}", err),
+        }
+
+        let replay = ReplayExecutor::new(&path).unwrap();
+        let req = RequestBuilder::json("sendMessage", &serde_json::json!({"chat_id": 1, "text": "other"}))
+            .unwrap()
+            .build("https://api.telegram.org", "123:token");
+        let err = replay.execute(req).wait().unwrap_err();
+        match err.downcast::<CassetteError>().unwrap() {
+            CassetteError::Mismatch { .. } => {}
+            err => panic!("Unexpected error: {:?}", err),
+        }
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn replay_concurrent() {
+        let path = cassette_path("concurrent");
+        let recorder = RecordingExecutor::new(ExecutorMock, &path).unwrap();
+        let slow = RequestBuilder::empty("slow").unwrap().build("host://", "123:token");
+        let fast = json_request("123:token");
+        let (slow, fast) = recorder.execute(slow).join(recorder.execute(fast)).wait().unwrap();
+        assert_eq!(slow, b"slow");
+        assert_eq!(fast, br#"{"ok":true,"result":true}"#);
+        // interactions are written in completion order
+        let data = fs::read_to_string(&path).unwrap();
+        assert!(data.find("sendMessage").unwrap() < data.find("slow").unwrap());
+
+        let replay = ReplayExecutor::new(&path).unwrap();
+        let req = RequestBuilder::empty("slow").unwrap().build("host://", "456:other");
+        assert_eq!(replay.execute(req).wait().unwrap(), b"slow");
+        let data = replay.execute(json_request("456:other")).wait().unwrap();
+        assert_eq!(data, br#"{"ok":true,"result":true}"#);
+        assert_eq!(replay.remaining(), 0);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn redact_body() {
+        let path = cassette_path("redact");
+        let request = |token: &str| {
+            let url = format!("https://example.com/{}/updates", token);
+            RequestBuilder::json("setWebhook", &serde_json::json!({ "url": url }))
+                .unwrap()
+                .build("https://api.telegram.org", token)
+        };
+        let recorder = RecordingExecutor::new(ExecutorMock, &path).unwrap();
+        recorder.execute(request("123:token")).wait().unwrap();
+        let data = fs::read_to_string(&path).unwrap();
+        assert!(!data.contains("123:token"));
+        assert!(data.contains("https://example.com/<token>/updates"));
+
+        let replay = ReplayExecutor::new(&path).unwrap();
+        replay.execute(request("456:other")).wait().unwrap();
+        fs::remove_file(path).unwrap();
+    }
+}
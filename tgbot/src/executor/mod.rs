@@ -1,11 +1,47 @@
 use crate::request::Request;
 use failure::Error;
-use futures::Future;
+use futures::{Future, Stream};
 
+mod cassette;
+#[cfg(feature = "proxy")]
+mod failover;
 mod hyper;
+#[cfg(feature = "proxy")]
+mod proxy;
+mod scheduler;
+mod timeout;
+mod tls;
 
-pub(crate) use self::hyper::{default_executor, proxy_executor};
+pub use self::{
+    cassette::{CassetteError, RecordingExecutor, ReplayExecutor},
+    scheduler::SendLimits,
+    timeout::TimeoutError,
+    tls::Certificate,
+};
+#[cfg(feature = "proxy")]
+pub(crate) use self::{
+    failover::{hide_password, FailoverExecutor, ProxyList},
+    proxy::proxy_executor,
+};
+pub(crate) use self::{
+    hyper::default_executor,
+    scheduler::Scheduler,
+    timeout::TimeoutExecutor,
+    tls::{tls_connector, ConnectConfig},
+};
 
-pub(crate) trait Executor: Send + Sync {
+/// Executes HTTP requests built by [Api](struct.Api.html)
+///
+/// Implement this trait in order to use your own HTTP client
+/// (see [Api::with_executor()](struct.Api.html#method.with_executor))
+pub trait Executor: Send + Sync {
+    /// Sends a request and returns a future with a raw response body
     fn execute(&self, req: Request) -> Box<dyn Future<Item = Vec<u8>, Error = Error> + Send>;
+
+    /// Sends a request and returns a stream of response body chunks
+    ///
+    /// Used to download files, default implementation yields a whole body as a single chunk
+    fn execute_stream(&self, req: Request) -> Box<dyn Stream<Item = Vec<u8>, Error = Error> + Send> {
+        Box::new(self.execute(req).into_stream())
+    }
 }
@@ -0,0 +1,339 @@
+use crate::{executor::Executor, request::Request, types::ChatId};
+use failure::Error;
+use futures::{Future, Stream};
+use std::{
+    cmp::max,
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+use tokio_timer::Delay;
+
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Limits for outgoing messages
+///
+/// Requests sending messages (`forwardMessage` and `send*` methods except `sendChatAction`)
+/// are delayed in order to fit the limits, other requests are sent immediately.
+///
+/// Default values are taken from the Bot FAQ:
+/// 30 messages per second globally, 1 message per second for a private chat
+/// and 20 messages per minute for a group or a channel.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SendLimits {
+    global: Limit,
+    private_chat: Limit,
+    group: Limit,
+}
+
+impl SendLimits {
+    /// Maximum number of messages to send during a period for all chats
+    pub fn global(mut self, count: usize, period: Duration) -> Self {
+        self.global = Limit { count, period };
+        self
+    }
+
+    /// Maximum number of messages to send during a period for a private chat
+    pub fn private_chat(mut self, count: usize, period: Duration) -> Self {
+        self.private_chat = Limit { count, period };
+        self
+    }
+
+    /// Maximum number of messages to send during a period for a group, supergroup or a channel
+    pub fn group(mut self, count: usize, period: Duration) -> Self {
+        self.group = Limit { count, period };
+        self
+    }
+}
+
+impl Default for SendLimits {
+    fn default() -> Self {
+        SendLimits {
+            global: Limit {
+                count: 30,
+                period: Duration::from_secs(1),
+            },
+            private_chat: Limit {
+                count: 1,
+                period: Duration::from_secs(1),
+            },
+            group: Limit {
+                count: 20,
+                period: Duration::from_secs(60),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Limit {
+    count: usize,
+    period: Duration,
+}
+
+/// Sent and scheduled times of messages, sorted
+///
+/// Slots are reserved in order, so a next slot depends only on the last `limit.count` ones
+struct Window {
+    limit: Limit,
+    slots: VecDeque<Instant>,
+}
+
+impl Window {
+    fn new(limit: Limit) -> Self {
+        Window {
+            limit,
+            slots: VecDeque::with_capacity(limit.count),
+        }
+    }
+
+    /// Returns the earliest free slot not earlier than a given time and the last reserved one
+    fn next_slot(&self, now: Instant) -> Instant {
+        let slot = self.slots.back().map_or(now, |last| max(now, *last));
+        let count = self.limit.count;
+        if count == 0 || self.slots.len() < count {
+            return slot;
+        }
+        // a slot becomes free when a message sent `count` messages ago leaves the period
+        max(slot, self.slots[self.slots.len() - count] + self.limit.period)
+    }
+
+    fn reserve(&mut self, slot: Instant) {
+        self.slots.push_back(slot);
+    }
+
+    /// Forgets slots which do not affect messages sent after a given time
+    fn prune(&mut self, now: Instant) {
+        while self
+            .slots
+            .front()
+            .map(|slot| *slot + self.limit.period <= now)
+            .unwrap_or(false)
+        {
+            self.slots.pop_front();
+        }
+    }
+
+    fn is_expired(&self, now: Instant) -> bool {
+        self.slots
+            .back()
+            .map(|last| *last + self.limit.period <= now)
+            .unwrap_or(true)
+    }
+}
+
+struct SchedulerState {
+    limits: SendLimits,
+    global: Window,
+    chats: HashMap<ChatId, Window>,
+    pruned_at: Instant,
+}
+
+impl SchedulerState {
+    fn new(limits: SendLimits, now: Instant) -> Self {
+        SchedulerState {
+            limits,
+            global: Window::new(limits.global),
+            chats: HashMap::new(),
+            pruned_at: now,
+        }
+    }
+
+    /// Returns time when a message for a given chat fits the chat limit
+    ///
+    /// Messages to a chat are sent in order, other chats are not delayed by them
+    fn reserve_chat(&mut self, now: Instant, chat_id: ChatId) -> Instant {
+        if now >= self.pruned_at + PRUNE_INTERVAL {
+            self.chats.retain(|_, window| !window.is_expired(now));
+            self.pruned_at = now;
+        }
+        let limit = match chat_id {
+            ChatId::Id(id) if id > 0 => self.limits.private_chat,
+            _ => self.limits.group,
+        };
+        let chat = self.chats.entry(chat_id).or_insert_with(|| Window::new(limit));
+        chat.prune(now);
+        let slot = chat.next_slot(now);
+        chat.reserve(slot);
+        slot
+    }
+
+    /// Returns time when a message fits the global limit
+    ///
+    /// Called when a chat slot is reached, so messages delayed by a chat limit do not take global slots
+    fn reserve_global(&mut self, now: Instant) -> Instant {
+        self.global.prune(now);
+        let slot = self.global.next_slot(now);
+        self.global.reserve(slot);
+        slot
+    }
+}
+
+fn is_send_method(name: &str) -> bool {
+    (name.starts_with("send") && name != "sendChatAction") || name == "forwardMessage"
+}
+
+/// Delays requests in order to fit send limits
+pub(crate) struct Scheduler {
+    executor: Arc<Box<dyn Executor>>,
+    state: Arc<Mutex<SchedulerState>>,
+}
+
+impl Scheduler {
+    pub(crate) fn new(executor: Box<dyn Executor>, limits: SendLimits) -> Self {
+        Scheduler {
+            executor: Arc::new(executor),
+            state: Arc::new(Mutex::new(SchedulerState::new(limits, Instant::now()))),
+        }
+    }
+}
+
+fn lock(state: &Mutex<SchedulerState>) -> MutexGuard<'_, SchedulerState> {
+    state.lock().expect("Scheduler state is poisoned")
+}
+
+impl Executor for Scheduler {
+    fn execute(&self, req: Request) -> Box<dyn Future<Item = Vec<u8>, Error = Error> + Send> {
+        let is_send = req.url.rsplit('/').next().map(is_send_method).unwrap_or(false);
+        let chat_id = if is_send { req.get_chat_id() } else { None };
+        let chat_id = match chat_id {
+            Some(chat_id) => chat_id,
+            None => return self.executor.execute(req),
+        };
+        let now = Instant::now();
+        let chat_slot = lock(&self.state).reserve_chat(now, chat_id);
+        if chat_slot <= now {
+            let slot = lock(&self.state).reserve_global(now);
+            if slot <= now {
+                return self.executor.execute(req);
+            }
+            log::debug!("Request {} is delayed for {:?}", req.url, slot - now);
+            let executor = self.executor.clone();
+            return Box::new(Delay::new(slot).from_err().and_then(move |()| executor.execute(req)));
+        }
+        log::debug!("Request {} is delayed for {:?}", req.url, chat_slot - now);
+        let state = self.state.clone();
+        let executor = self.executor.clone();
+        Box::new(
+            Delay::new(chat_slot)
+                .and_then(move |()| Delay::new(lock(&state).reserve_global(Instant::now())))
+                .from_err()
+                .and_then(move |()| executor.execute(req)),
+        )
+    }
+
+    fn execute_stream(&self, req: Request) -> Box<dyn Stream<Item = Vec<u8>, Error = Error> + Send> {
+        // streams are used for downloads only, they are not limited
+        self.executor.execute_stream(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(value: u64) -> Duration {
+        Duration::from_millis(value)
+    }
+
+    #[test]
+    fn send_method() {
+        assert!(is_send_method("sendMessage"));
+        assert!(is_send_method("sendMediaGroup"));
+        assert!(is_send_method("forwardMessage"));
+        assert!(!is_send_method("sendChatAction"));
+        assert!(!is_send_method("getMe"));
+        assert!(!is_send_method("getFile"));
+    }
+
+    #[test]
+    fn window() {
+        let now = Instant::now();
+        let mut window = Window::new(Limit {
+            count: 2,
+            period: ms(1000),
+        });
+        assert!(window.is_expired(now));
+        assert_eq!(window.next_slot(now), now);
+        window.reserve(now);
+        assert_eq!(window.next_slot(now), now);
+        window.reserve(now + ms(100));
+        assert_eq!(window.next_slot(now), now + ms(1000));
+        window.reserve(now + ms(1000));
+        assert_eq!(window.next_slot(now), now + ms(1100));
+        assert!(!window.is_expired(now + ms(1100)));
+        assert!(window.is_expired(now + ms(2000)));
+    }
+
+    #[test]
+    fn limits() {
+        let now = Instant::now();
+        let limits = SendLimits::default()
+            .global(3, ms(1000))
+            .private_chat(1, ms(500))
+            .group(2, ms(2000));
+        let mut state = SchedulerState::new(limits, now);
+        assert_eq!(state.reserve_chat(now, ChatId::Id(1)), now);
+        assert_eq!(state.reserve_chat(now, ChatId::Id(1)), now + ms(500));
+        assert_eq!(state.reserve_chat(now, ChatId::Id(2)), now);
+        assert_eq!(state.reserve_chat(now, ChatId::Id(-1)), now);
+        assert_eq!(state.reserve_chat(now, ChatId::Id(-1)), now);
+        assert_eq!(state.reserve_chat(now, ChatId::from("@channel")), now);
+        // group limit is reached
+        assert_eq!(state.reserve_chat(now, ChatId::Id(-1)), now + ms(2000));
+
+        for _ in 0..3 {
+            assert_eq!(state.reserve_global(now), now);
+        }
+        // global limit is reached
+        assert_eq!(state.reserve_global(now), now + ms(1000));
+        assert_eq!(state.reserve_global(now), now + ms(1000));
+        assert_eq!(state.reserve_global(now + ms(500)), now + ms(1000));
+        assert_eq!(state.reserve_global(now + ms(500)), now + ms(2000));
+
+        assert_eq!(state.chats.len(), 4);
+        state.reserve_chat(now + PRUNE_INTERVAL, ChatId::Id(3));
+        assert_eq!(state.chats.len(), 1);
+    }
+
+    #[test]
+    fn throttled_chat() {
+        let now = Instant::now();
+        let limits = SendLimits::default().global(30, ms(1000)).private_chat(1, ms(1000));
+        let mut state = SchedulerState::new(limits, now);
+        for idx in 0..5 {
+            assert_eq!(state.reserve_chat(now, ChatId::Id(1)), now + ms(1000 * idx));
+        }
+        assert_eq!(state.reserve_global(now), now);
+        // another chat is not waiting for throttled one
+        assert_eq!(state.reserve_chat(now, ChatId::Id(2)), now);
+        assert_eq!(state.reserve_global(now), now);
+        assert_eq!(state.reserve_chat(now + ms(100), ChatId::Id(3)), now + ms(100));
+        assert_eq!(state.reserve_global(now + ms(100)), now + ms(100));
+        assert_eq!(state.reserve_chat(now + ms(100), ChatId::Id(1)), now + ms(5000));
+    }
+
+    #[test]
+    fn broadcast() {
+        let now = Instant::now();
+        let mut state = SchedulerState::new(SendLimits::default(), now);
+        let mut slot = now;
+        for id in 1..=10_000 {
+            assert_eq!(state.reserve_chat(now, ChatId::Id(id)), now);
+            slot = state.reserve_global(now);
+        }
+        // 30 messages per second
+        assert_eq!(slot, now + Duration::from_secs(333));
+        assert_eq!(state.global.slots.len(), 10_000);
+        state.reserve_global(now + Duration::from_secs(333));
+        assert_eq!(state.global.slots.len(), 11);
+    }
+
+    #[test]
+    fn default_limits() {
+        let limits = SendLimits::default();
+        assert_eq!(limits.global.count, 30);
+        assert_eq!(limits.private_chat.count, 1);
+        assert_eq!(limits.group.period, Duration::from_secs(60));
+    }
+}
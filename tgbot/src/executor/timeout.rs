@@ -0,0 +1,100 @@
+use crate::{executor::Executor, request::Request};
+use failure::Error;
+use futures::{Future, Stream};
+use std::time::Duration;
+use tokio_timer::{timeout::Error as TimeoutTimerError, Timeout};
+
+/// A request has not been completed in time
+///
+/// See [Config::request_timeout()](struct.Config.html#method.request_timeout)
+#[derive(Clone, Copy, Debug, failure::Fail)]
+#[fail(display = "Request timed out after {:?}", timeout)]
+pub struct TimeoutError {
+    /// Timeout of the request
+    pub timeout: Duration,
+}
+
+/// Cancels requests exceeded `Request::timeout`
+///
+/// For streams timeout is applied to each chunk
+pub(crate) struct TimeoutExecutor {
+    executor: Box<dyn Executor>,
+}
+
+impl TimeoutExecutor {
+    pub(crate) fn new(executor: Box<dyn Executor>) -> Self {
+        TimeoutExecutor { executor }
+    }
+}
+
+impl Executor for TimeoutExecutor {
+    fn execute(&self, req: Request) -> Box<dyn Future<Item = Vec<u8>, Error = Error> + Send> {
+        match req.timeout {
+            Some(timeout) => Box::new(
+                Timeout::new(self.executor.execute(req), timeout).map_err(move |err| convert_error(err, timeout)),
+            ),
+            None => self.executor.execute(req),
+        }
+    }
+
+    fn execute_stream(&self, req: Request) -> Box<dyn Stream<Item = Vec<u8>, Error = Error> + Send> {
+        match req.timeout {
+            Some(timeout) => Box::new(
+                Timeout::new(self.executor.execute_stream(req), timeout)
+                    .map_err(move |err| convert_error(err, timeout)),
+            ),
+            None => self.executor.execute_stream(req),
+        }
+    }
+}
+
+fn convert_error(err: TimeoutTimerError<Error>, timeout: Duration) -> Error {
+    if err.is_elapsed() {
+        TimeoutError { timeout }.into()
+    } else if err.is_inner() {
+        err.into_inner().expect("Can not get an inner error")
+    } else {
+        err.into_timer().expect("Can not get a timer error").into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::RequestBuilder;
+    use futures::future;
+    use tokio::runtime::current_thread::block_on_all;
+
+    struct ExecutorMock;
+
+    impl Executor for ExecutorMock {
+        fn execute(&self, req: Request) -> Box<dyn Future<Item = Vec<u8>, Error = Error> + Send> {
+            if req.url.ends_with("empty") {
+                Box::new(future::empty())
+            } else {
+                Box::new(future::ok(b"data".to_vec()))
+            }
+        }
+    }
+
+    fn request(path: &str, timeout: Option<Duration>) -> Request {
+        let mut req = RequestBuilder::empty(path).unwrap().build("host", "token");
+        req.timeout = timeout;
+        req
+    }
+
+    #[test]
+    fn timeout() {
+        let executor = TimeoutExecutor::new(Box::new(ExecutorMock));
+        let timeout = Duration::from_millis(10);
+
+        let err = block_on_all(executor.execute(request("empty", Some(timeout)))).unwrap_err();
+        assert_eq!(err.downcast::<TimeoutError>().unwrap().timeout, timeout);
+
+        let data = block_on_all(executor.execute(request("data", Some(timeout)))).unwrap();
+        assert_eq!(data, b"data");
+
+        let data = block_on_all(executor.execute(request("data", None))).unwrap();
+        assert_eq!(data, b"data");
+    }
+}
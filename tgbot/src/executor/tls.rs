@@ -0,0 +1,199 @@
+use failure::Error;
+use std::time::Duration;
+
+#[cfg(not(any(feature = "default-tls", feature = "rustls-tls")))]
+compile_error!("Either default-tls or rustls-tls feature must be enabled");
+
+/// A root certificate trusted in addition to system (default-tls) or Mozilla (rustls-tls) roots
+///
+/// Use it in order to connect to a server with a self-signed certificate,
+/// e.g. a local Bot API server
+///
+/// rustls can not verify certificates issued for IP addresses,
+/// so with rustls-tls feature a host given by IP address must present one of these certificates
+#[derive(Clone)]
+pub struct Certificate {
+    der: Vec<u8>,
+}
+
+impl Certificate {
+    /// Creates a certificate from DER data
+    pub fn from_der<D: Into<Vec<u8>>>(der: D) -> Self {
+        Certificate { der: der.into() }
+    }
+
+    /// Creates a certificate from PEM data
+    ///
+    /// The first certificate is used when data contains several ones
+    #[cfg(feature = "rustls-tls")]
+    pub fn from_pem(pem: &[u8]) -> Result<Self, Error> {
+        match rustls::internal::pemfile::certs(&mut &pem[..]) {
+            Ok(ref mut certs) if !certs.is_empty() => Ok(Certificate::from_der(certs.remove(0).0)),
+            _ => Err(PemError.into()),
+        }
+    }
+
+    /// Creates a certificate from PEM data
+    ///
+    /// The first certificate is used when data contains several ones
+    #[cfg(all(feature = "default-tls", not(feature = "rustls-tls")))]
+    pub fn from_pem(pem: &[u8]) -> Result<Self, Error> {
+        Ok(Certificate::from_der(native_tls::Certificate::from_pem(pem)?.to_der()?))
+    }
+}
+
+impl std::fmt::Debug for Certificate {
+    fn fmt(&self, out: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(out, "Certificate({} bytes)", self.der.len())
+    }
+}
+
+#[cfg(feature = "rustls-tls")]
+#[derive(Debug, failure::Fail)]
+#[fail(display = "PEM data does not contain a certificate")]
+struct PemError;
+
+/// Settings for creating connections
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ConnectConfig {
+    pub(crate) connect_timeout: Option<Duration>,
+    pub(crate) root_certificates: Vec<Certificate>,
+}
+
+// native-tls is used with default-tls feature, rustls is used with rustls-tls feature;
+// connectors wrap any other connector, so TLS works the same way
+// for direct connections, HTTP proxy tunnels and SOCKS proxies
+#[cfg(feature = "rustls-tls")]
+pub(crate) use self::rustls_backend::tls_connector;
+
+#[cfg(all(feature = "default-tls", not(feature = "rustls-tls")))]
+pub(crate) use self::native_backend::tls_connector;
+
+// only compile_error above is reported when no backend is enabled
+#[cfg(not(any(feature = "default-tls", feature = "rustls-tls")))]
+pub(crate) fn tls_connector<C>(connector: C, _config: &ConnectConfig) -> Result<C, Error> {
+    Ok(connector)
+}
+
+#[cfg(all(feature = "default-tls", not(feature = "rustls-tls")))]
+mod native_backend {
+    use super::ConnectConfig;
+    use failure::Error;
+    use hyper_tls::HttpsConnector;
+    use native_tls::{Certificate, TlsConnector as NativeTlsConnector};
+
+    pub(crate) type TlsConnector<C> = HttpsConnector<C>;
+
+    /// Wraps a connector, so https destinations are connected using TLS
+    pub(crate) fn tls_connector<C>(connector: C, config: &ConnectConfig) -> Result<TlsConnector<C>, Error> {
+        let mut builder = NativeTlsConnector::builder();
+        for certificate in &config.root_certificates {
+            builder.add_root_certificate(Certificate::from_der(&certificate.der)?);
+        }
+        Ok(HttpsConnector::from((connector, builder.build()?)))
+    }
+}
+
+#[cfg(feature = "rustls-tls")]
+mod rustls_backend {
+    use super::ConnectConfig;
+    use failure::Error;
+    use futures::Future;
+    use hyper::client::connect::{Connect, Connected, Destination};
+    use hyper_rustls::{HttpsConnector, MaybeHttpsStream};
+    use rustls::{Certificate, ClientConfig, RootCertStore, ServerCertVerified, ServerCertVerifier, TLSError};
+    use std::{io, net::IpAddr, sync::Arc};
+    use tokio_rustls::TlsConnector as RustlsConnector;
+    use webpki::DNSNameRef;
+
+    /// Wraps a connector, so https destinations are connected using TLS
+    pub(crate) fn tls_connector<C>(connector: C, config: &ConnectConfig) -> Result<TlsConnector<C>, Error>
+    where
+        C: Clone,
+    {
+        let mut tls_config = ClientConfig::new();
+        tls_config
+            .root_store
+            .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+        for certificate in &config.root_certificates {
+            tls_config
+                .root_store
+                .add(&Certificate(certificate.der.clone()))
+                .map_err(|err| failure::err_msg(format!("Invalid root certificate: {:?}", err)))?;
+        }
+        let mut ip_config = ClientConfig::new();
+        ip_config.enable_sni = false;
+        ip_config.dangerous().set_certificate_verifier(Arc::new(PinnedVerifier {
+            certificates: config.root_certificates.iter().map(|cert| cert.der.clone()).collect(),
+        }));
+        Ok(TlsConnector {
+            https: HttpsConnector::from((connector.clone(), tls_config)),
+            connector,
+            ip_config: Arc::new(ip_config),
+        })
+    }
+
+    /// Connects to IP addresses using pinned certificates, to other hosts using hyper-rustls
+    #[derive(Clone)]
+    pub(crate) struct TlsConnector<C> {
+        https: HttpsConnector<C>,
+        connector: C,
+        ip_config: Arc<ClientConfig>,
+    }
+
+    impl<C> Connect for TlsConnector<C>
+    where
+        C: Connect<Error = io::Error>,
+        C::Transport: 'static,
+        C::Future: 'static,
+    {
+        type Transport = MaybeHttpsStream<C::Transport>;
+        type Error = io::Error;
+        type Future = Box<dyn Future<Item = (Self::Transport, Connected), Error = io::Error> + Send>;
+
+        fn connect(&self, dst: Destination) -> Self::Future {
+            let is_ip = dst
+                .host()
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .parse::<IpAddr>()
+                .is_ok();
+            if dst.scheme() != "https" || !is_ip {
+                return Box::new(self.https.connect(dst));
+            }
+            let tls = RustlsConnector::from(self.ip_config.clone());
+            Box::new(self.connector.connect(dst).and_then(move |(io, connected)| {
+                // SNI is disabled and name is ignored by PinnedVerifier
+                let name = DNSNameRef::try_from_ascii_str("localhost").expect("Invalid DNS name");
+                tls.connect(name, io)
+                    .map(move |stream| (MaybeHttpsStream::Https(stream), connected))
+            }))
+        }
+    }
+
+    /// Accepts root certificates added to Config only
+    ///
+    /// webpki can not verify certificates issued for IP addresses,
+    /// so a server must present one of trusted certificates, e.g. a self-signed one
+    struct PinnedVerifier {
+        certificates: Vec<Vec<u8>>,
+    }
+
+    impl ServerCertVerifier for PinnedVerifier {
+        fn verify_server_cert(
+            &self,
+            _roots: &RootCertStore,
+            presented_certs: &[Certificate],
+            _dns_name: DNSNameRef,
+            _ocsp_response: &[u8],
+        ) -> Result<ServerCertVerified, TLSError> {
+            match presented_certs.first() {
+                Some(cert) if self.certificates.contains(&cert.0) => Ok(ServerCertVerified::assertion()),
+                Some(_) => Err(TLSError::General(String::from(
+                    "Certificate of an IP address host must be added to root certificates",
+                ))),
+                None => Err(TLSError::NoCertificatesPresented),
+            }
+        }
+    }
+}
@@ -0,0 +1,202 @@
+#[cfg(feature = "webhook")]
+use crate::handler::WebhookReply;
+use crate::{
+    handler::UpdateHandler,
+    types::{Integer, Update},
+};
+use futures::Future;
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// Keeps identifiers of received updates
+pub trait UpdateIdStore: Send {
+    /// Remembers an identifier
+    ///
+    /// Returns false when identifier is remembered already
+    fn insert(&mut self, update_id: Integer) -> bool;
+}
+
+/// Keeps a limited number of recently received identifiers in memory
+#[derive(Clone, Debug)]
+pub struct RecentUpdateIds {
+    capacity: usize,
+    ids: HashSet<Integer>,
+    order: VecDeque<Integer>,
+}
+
+impl RecentUpdateIds {
+    /// Creates a new store
+    ///
+    /// # Arguments
+    ///
+    /// * capacity - How many identifiers to keep, the oldest one is forgotten when capacity is exceeded
+    pub fn new(capacity: usize) -> Self {
+        RecentUpdateIds {
+            capacity,
+            ids: HashSet::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+        }
+    }
+}
+
+impl UpdateIdStore for RecentUpdateIds {
+    fn insert(&mut self, update_id: Integer) -> bool {
+        if !self.ids.insert(update_id) {
+            return false;
+        }
+        self.order.push_back(update_id);
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.ids.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+/// Drops updates which have been received already
+///
+/// Telegram delivers an update again when webhook has not responded in time
+/// or when getUpdates has failed after updates were sent.
+/// See [UpdateMethod::deduplicate()](struct.UpdateMethod.html#method.deduplicate).
+/// Clones share the same store and counter.
+#[derive(Clone)]
+pub struct Deduplicator {
+    store: Arc<Mutex<Box<dyn UpdateIdStore>>>,
+    dropped: Arc<AtomicUsize>,
+}
+
+impl Deduplicator {
+    /// Creates a deduplicator which remembers a given number of recent updates
+    pub fn new(capacity: usize) -> Self {
+        Self::with_store(RecentUpdateIds::new(capacity))
+    }
+
+    /// Creates a deduplicator with a custom store
+    pub fn with_store<S: UpdateIdStore + 'static>(store: S) -> Self {
+        Deduplicator {
+            store: Arc::new(Mutex::new(Box::new(store))),
+            dropped: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Returns a number of dropped updates
+    pub fn get_dropped_count(&self) -> usize {
+        self.dropped.load(Ordering::SeqCst)
+    }
+
+    fn is_duplicate(&self, update: &Update) -> bool {
+        let is_new = self
+            .store
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .insert(update.id);
+        if !is_new {
+            let count = self.dropped.fetch_add(1, Ordering::SeqCst) + 1;
+            log::warn!("Dropped duplicate update {} ({} dropped so far)", update.id, count);
+        }
+        !is_new
+    }
+}
+
+/// Passes an update to handler unless it is a duplicate
+pub(super) struct DedupHandler<H> {
+    handler: H,
+    deduplicator: Option<Deduplicator>,
+}
+
+impl<H: UpdateHandler> DedupHandler<H> {
+    pub(super) fn new(handler: H, deduplicator: Option<Deduplicator>) -> Self {
+        DedupHandler { handler, deduplicator }
+    }
+
+    fn is_duplicate(&self, update: &Update) -> bool {
+        match self.deduplicator {
+            Some(ref deduplicator) => deduplicator.is_duplicate(update),
+            None => false,
+        }
+    }
+}
+
+impl<H: UpdateHandler> UpdateHandler for DedupHandler<H> {
+    fn handle(&mut self, update: Update) {
+        if !self.is_duplicate(&update) {
+            self.handler.handle(update)
+        }
+    }
+
+    #[cfg(feature = "webhook")]
+    fn handle_with_reply(&mut self, update: Update, reply: WebhookReply) {
+        if !self.is_duplicate(&update) {
+            self.handler.handle_with_reply(update, reply)
+        }
+    }
+
+    fn drain(&mut self) -> Box<dyn Future<Item = (), Error = ()> + Send> {
+        self.handler.drain()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    struct Handler {
+        updates: Vec<Integer>,
+    }
+
+    impl UpdateHandler for Handler {
+        fn handle(&mut self, update: Update) {
+            self.updates.push(update.id)
+        }
+    }
+
+    fn update(id: Integer) -> Update {
+        serde_json::from_value(json!({
+            "update_id": id,
+            "message": {
+                "message_id": id,
+                "date": 0,
+                "from": {"id": 1, "is_bot": false, "first_name": "test"},
+                "chat": {"id": 1, "type": "private", "first_name": "test"},
+                "text": "test"
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn recent_update_ids() {
+        let mut store = RecentUpdateIds::new(2);
+        assert!(store.insert(1));
+        assert!(store.insert(2));
+        assert!(!store.insert(1));
+        assert!(store.insert(3));
+        // the oldest identifier is forgotten
+        assert!(store.insert(1));
+        assert!(!store.insert(3));
+    }
+
+    #[test]
+    fn dedup_handler() {
+        let deduplicator = Deduplicator::new(10);
+        let mut handler = DedupHandler::new(Handler { updates: Vec::new() }, Some(deduplicator.clone()));
+        for id in &[1, 2, 1, 3, 2] {
+            handler.handle(update(*id));
+        }
+        assert_eq!(handler.handler.updates, vec![1, 2, 3]);
+        assert_eq!(deduplicator.get_dropped_count(), 2);
+
+        let mut handler = DedupHandler::new(Handler { updates: Vec::new() }, None);
+        for id in &[1, 1] {
+            handler.handle(update(*id));
+        }
+        assert_eq!(handler.handler.updates, vec![1, 1]);
+    }
+}
@@ -1,12 +1,52 @@
+#[cfg(feature = "webhook")]
+use crate::api::WebhookConfig;
 use crate::types::Update;
-use futures::{future::Either, Future, Stream};
+#[cfg(feature = "polling")]
+use futures::Stream;
+use futures::{future, Future};
+#[cfg(feature = "webhook")]
 use hyper::Server;
+#[cfg(feature = "webhook")]
 use std::net::SocketAddr;
+#[cfg(any(feature = "polling", feature = "webhook"))]
+use std::time::Duration;
 
+#[cfg(any(feature = "polling", feature = "webhook"))]
+mod dedup;
+#[cfg(feature = "polling")]
+mod offset;
+#[cfg(feature = "polling")]
 mod poll;
+#[cfg(feature = "webhook")]
+mod registration;
+#[cfg(feature = "webhook")]
+mod reply;
+#[cfg(any(feature = "polling", feature = "webhook"))]
+mod shutdown;
+#[cfg(feature = "webhook")]
 mod webhook;
 
-pub use self::{poll::*, webhook::*};
+#[cfg(any(feature = "polling", feature = "webhook"))]
+pub use self::dedup::{Deduplicator, RecentUpdateIds, UpdateIdStore};
+#[cfg(feature = "polling")]
+pub use self::offset::*;
+#[cfg(feature = "polling")]
+pub use self::poll::*;
+#[cfg(feature = "webhook")]
+pub use self::registration::*;
+#[cfg(feature = "webhook")]
+pub use self::reply::*;
+#[cfg(feature = "webhook")]
+pub use self::webhook::*;
+
+#[cfg(any(feature = "polling", feature = "webhook"))]
+use self::{
+    dedup::DedupHandler,
+    shutdown::{BoxedFuture, SharedHandler, ShutdownSignal},
+};
+
+#[cfg(any(feature = "polling", feature = "webhook"))]
+const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// An update handler
 pub trait UpdateHandler {
@@ -16,19 +56,42 @@ pub trait UpdateHandler {
     ///
     /// * update - A received update
     fn handle(&mut self, update: Update);
+
+    /// Handles an update received by webhook when replies are enabled
+    ///
+    /// A method sent using `reply` is executed by Telegram as a response to the webhook request,
+    /// see [UpdateMethod::reply_timeout()](struct.UpdateMethod.html#method.reply_timeout).
+    /// Default implementation calls [handle()](#tymethod.handle), so response is empty.
+    #[cfg(feature = "webhook")]
+    fn handle_with_reply(&mut self, update: Update, reply: WebhookReply) {
+        drop(reply);
+        self.handle(update)
+    }
+
+    /// Returns a future which resolves when handling of received updates is finished
+    ///
+    /// Called on graceful shutdown, see [UpdateMethod::shutdown_signal()](struct.UpdateMethod.html#method.shutdown_signal).
+    /// Handlers spawning futures should wait for them, default implementation resolves immediately.
+    fn drain(&mut self) -> Box<dyn Future<Item = (), Error = ()> + Send> {
+        Box::new(future::ok(()))
+    }
 }
 
 /// Defines how to get updates from Telegram
+#[cfg(any(feature = "polling", feature = "webhook"))]
 pub struct UpdateMethod {
     kind: UpdateMethodKind,
+    shutdown_signal: Option<ShutdownSignal>,
+    shutdown_timeout: Duration,
+    deduplicator: Option<Deduplicator>,
 }
 
+#[cfg(any(feature = "polling", feature = "webhook"))]
 impl UpdateMethod {
     /// Get updates using long polling
+    #[cfg(feature = "polling")]
     pub fn poll<S: Into<UpdatesStream>>(stream: S) -> Self {
-        Self {
-            kind: UpdateMethodKind::Poll(stream.into()),
-        }
+        Self::new(UpdateMethodKind::Poll(stream.into()))
     }
 
     /// Get updates using a webhook
@@ -37,48 +100,247 @@ impl UpdateMethod {
     ///
     /// * addr - Bind address
     /// * path - URL path for webhook
+    #[cfg(feature = "webhook")]
     pub fn webhook<A, S>(addr: A, path: S) -> Self
     where
         A: Into<SocketAddr>,
         S: Into<String>,
     {
+        Self::new(UpdateMethodKind::Webhook(WebhookOptions {
+            addr: addr.into(),
+            path: path.into(),
+            secret_token: None,
+            reply_timeout: None,
+            registration: None,
+            queue_size: None,
+            queue_overflow: None,
+            status_routes: false,
+        }))
+    }
+
+    fn new(kind: UpdateMethodKind) -> Self {
         Self {
-            kind: UpdateMethodKind::Webhook {
-                addr: addr.into(),
-                path: path.into(),
-            },
+            kind,
+            shutdown_signal: None,
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            deduplicator: None,
         }
     }
+
+    /// Sets a secret token for webhook
+    ///
+    /// Requests without a matching `X-Telegram-Bot-Api-Secret-Token` header are rejected with 401.
+    /// Pass the same value to [SetWebhook::secret_token](methods/struct.SetWebhook.html#method.secret_token).
+    /// Has no effect when updates are received using long polling.
+    #[cfg(feature = "webhook")]
+    pub fn secret_token<S: Into<String>>(self, secret_token: S) -> Self {
+        self.with_webhook(|options| options.secret_token = Some(secret_token.into()))
+    }
+
+    /// Sets webhook on start using a given registration
+    ///
+    /// Secret token is passed to setWebhook as well.
+    /// Has no effect when updates are received using long polling.
+    #[cfg(feature = "webhook")]
+    pub fn register(self, registration: WebhookRegistration) -> Self {
+        self.with_webhook(|options| options.registration = Some(registration))
+    }
+
+    /// Stops updates handling gracefully when a given future resolves
+    ///
+    /// Long polling stops fetching updates, webhook server stops accepting connections.
+    /// Then received updates are processed and handler is drained (see [UpdateHandler::drain()](trait.UpdateHandler.html#method.drain))
+    /// and future returned by [handle_updates()](fn.handle_updates.html) resolves.
+    pub fn shutdown_signal<F>(mut self, signal: F) -> Self
+    where
+        F: Future<Item = (), Error = ()> + Send + 'static,
+    {
+        self.shutdown_signal = Some(Box::new(signal));
+        self
+    }
+
+    /// How long to wait for received updates to be processed on shutdown, defaults to 10 seconds
+    pub fn shutdown_timeout(mut self, shutdown_timeout: Duration) -> Self {
+        self.shutdown_timeout = shutdown_timeout;
+        self
+    }
+
+    /// Drops updates which have been received already, so handler is not called twice
+    ///
+    /// Keep a clone of deduplicator in order to get a number of dropped updates
+    pub fn deduplicate(mut self, deduplicator: Deduplicator) -> Self {
+        self.deduplicator = Some(deduplicator);
+        self
+    }
+
+    /// Allows update handler to reply with a method in a webhook response
+    ///
+    /// Telegram waits for a response up to 60 seconds, so timeout should be a few seconds.
+    /// Has no effect when updates are received using long polling.
+    #[cfg(feature = "webhook")]
+    pub fn reply_timeout(self, reply_timeout: Duration) -> Self {
+        self.with_webhook(|options| options.reply_timeout = Some(reply_timeout))
+    }
+
+    /// Maximum number of webhook updates waiting for handler, defaults to 10
+    ///
+    /// Zero is replaced with 1.
+    /// Has no effect when updates are received using long polling.
+    #[cfg(feature = "webhook")]
+    pub fn queue_size(self, queue_size: usize) -> Self {
+        self.with_webhook(|options| options.queue_size = Some(queue_size))
+    }
+
+    /// What to do with a webhook request when queue is full, defaults to waiting for a free slot
+    ///
+    /// Has no effect when updates are received using long polling.
+    #[cfg(feature = "webhook")]
+    pub fn queue_overflow(self, queue_overflow: QueueOverflow) -> Self {
+        self.with_webhook(|options| options.queue_overflow = Some(queue_overflow))
+    }
+
+    /// Enables health, readiness and stats routes on webhook server
+    ///
+    /// See [WebhookServiceFactory::status_routes()](struct.WebhookServiceFactory.html#method.status_routes).
+    /// Has no effect when updates are received using long polling.
+    #[cfg(feature = "webhook")]
+    pub fn status_routes(self, status_routes: bool) -> Self {
+        self.with_webhook(|options| options.status_routes = status_routes)
+    }
+
+    #[cfg(feature = "webhook")]
+    fn with_webhook<F: FnOnce(&mut WebhookOptions)>(mut self, f: F) -> Self {
+        match self.kind {
+            UpdateMethodKind::Webhook(ref mut options) => f(options),
+            #[cfg(feature = "polling")]
+            UpdateMethodKind::Poll(_) => {}
+        }
+        self
+    }
 }
 
+#[cfg(feature = "webhook")]
+impl From<WebhookConfig> for UpdateMethod {
+    fn from(config: WebhookConfig) -> Self {
+        let method = UpdateMethod::webhook(config.get_address(), config.get_path());
+        match config.get_secret_token() {
+            Some(secret_token) => method.secret_token(secret_token),
+            None => method,
+        }
+    }
+}
+
+#[cfg(any(feature = "polling", feature = "webhook"))]
 enum UpdateMethodKind {
+    #[cfg(feature = "polling")]
     Poll(UpdatesStream),
-    Webhook { addr: SocketAddr, path: String },
+    #[cfg(feature = "webhook")]
+    Webhook(WebhookOptions),
+}
+
+#[cfg(feature = "webhook")]
+struct WebhookOptions {
+    addr: SocketAddr,
+    path: String,
+    secret_token: Option<String>,
+    reply_timeout: Option<Duration>,
+    registration: Option<WebhookRegistration>,
+    queue_size: Option<usize>,
+    queue_overflow: Option<QueueOverflow>,
+    status_routes: bool,
 }
 
 /// Processes updates from Telegram
 ///
+/// Returned future resolves after graceful shutdown,
+/// see [UpdateMethod::shutdown_signal()](struct.UpdateMethod.html#method.shutdown_signal).
+/// Resolves with an error when webhook server fails or webhook can not be registered,
+/// see [UpdateMethod::register()](struct.UpdateMethod.html#method.register)
+///
 /// # Arguments
 ///
 /// * update_method - How to receive updates: via webhook or long polling
 /// * handler - Updates handler (see [UpdateHandler](trait.UpdateHandler.html) trait)
-pub fn handle_updates<H>(update_method: UpdateMethod, mut handler: H) -> impl Future<Item = (), Error = ()>
+#[cfg(any(feature = "polling", feature = "webhook"))]
+pub fn handle_updates<H>(update_method: UpdateMethod, handler: H) -> impl Future<Item = (), Error = ()>
 where
     H: UpdateHandler + Send + Sync + 'static,
 {
-    match update_method.kind {
-        UpdateMethodKind::Poll(stream) => Either::A(
-            stream
-                .for_each(move |update| {
-                    handler.handle(update);
+    let UpdateMethod {
+        kind,
+        shutdown_signal,
+        shutdown_timeout,
+        deduplicator,
+    } = update_method;
+    let handler = SharedHandler::new(DedupHandler::new(handler, deduplicator));
+    let drain_handler = handler.clone();
+    // resolves when updates source is stopped and returns a future resolving when received updates are processed
+    // and whether the source is stopped gracefully
+    let f: Box<dyn Future<Item = (BoxedFuture, bool), Error = ()> + Send> = match kind {
+        #[cfg(feature = "polling")]
+        UpdateMethodKind::Poll(stream) => {
+            let mut handler = handler;
+            Box::new(
+                shutdown::StoppableStream::new(stream, shutdown_signal)
+                    .for_each(move |update| {
+                        handler.handle(update);
+                        Ok(())
+                    })
+                    .then(|result| {
+                        if let Err(err) = result {
+                            log::error!("Failed to get updates: {}", err);
+                        }
+                        let processed: BoxedFuture = Box::new(future::ok(()));
+                        Ok((processed, true))
+                    }),
+            )
+        }
+        #[cfg(feature = "webhook")]
+        UpdateMethodKind::Webhook(options) => {
+            let mut factory = WebhookServiceFactory::new(options.path, handler);
+            if let Some(ref secret_token) = options.secret_token {
+                factory = factory.secret_token(secret_token.as_str());
+            }
+            if let Some(reply_timeout) = options.reply_timeout {
+                factory = factory.reply_timeout(reply_timeout);
+            }
+            if let Some(queue_size) = options.queue_size {
+                factory = factory.queue_size(queue_size);
+            }
+            if let Some(queue_overflow) = options.queue_overflow {
+                factory = factory.queue_overflow(queue_overflow);
+            }
+            factory = factory.status_routes(options.status_routes);
+            let processed = factory.processed();
+            let server = Server::bind(&options.addr).serve(factory);
+            let server: BoxedFuture = match shutdown_signal {
+                Some(signal) => Box::new(
+                    server
+                        .with_graceful_shutdown(signal)
+                        .map_err(|e| log::error!("Server error: {}", e)),
+                ),
+                None => Box::new(server.map_err(|e| log::error!("Server error: {}", e))),
+            };
+            // queued updates are processed after server is stopped
+            match options.registration {
+                Some(registration) => Box::new(
+                    registration
+                        .run(options.secret_token, server)
+                        .map(move |is_graceful| (processed, is_graceful)),
+                ),
+                None => Box::new(server.then(move |result| Ok((processed, result.is_ok())))),
+            }
+        }
+    };
+    f.and_then(move |(processed, is_graceful)| {
+        shutdown::drain(processed, drain_handler, shutdown_timeout).then(
+            move |_| {
+                if is_graceful {
                     Ok(())
-                })
-                .then(|_| Ok(())),
-        ),
-        UpdateMethodKind::Webhook { addr, path } => Either::B(
-            Server::bind(&addr)
-                .serve(WebhookServiceFactory::new(path, handler))
-                .map_err(|e| log::error!("Server error: {}", e)),
-        ),
-    }
+                } else {
+                    Err(())
+                }
+            },
+        )
+    })
 }
@@ -0,0 +1,168 @@
+use crate::{blocking::blocking, types::Integer};
+use failure::Error;
+use futures::{future, Future};
+use std::{
+    fs, io,
+    io::Write,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+/// Stores an identifier of the last processed update
+///
+/// Used by [UpdatesStream](struct.UpdatesStream.html) in order to resume polling after restart
+pub trait OffsetStore: Send + Sync {
+    /// Returns a stored identifier, None if nothing is stored yet
+    fn load(&self) -> Box<dyn Future<Item = Option<Integer>, Error = Error> + Send>;
+
+    /// Stores an identifier
+    fn save(&self, offset: Integer) -> Box<dyn Future<Item = (), Error = Error> + Send>;
+}
+
+/// When offset is saved to a store
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CommitPolicy {
+    /// After updates are received
+    ///
+    /// An update will not be received again after restart, even if it has not been handled
+    AfterFetch,
+    /// After an update is handled
+    ///
+    /// Update is considered handled when stream is polled for a next one,
+    /// e.g. when [UpdateHandler::handle()](trait.UpdateHandler.html#tymethod.handle) returns.
+    /// When a handler spawns a future per update (e.g. carapax dispatcher),
+    /// this means "after dispatch", the future may still be running.
+    ///
+    /// Offsets of updates handled while a previous offset is being saved are coalesced into a single save
+    AfterHandle,
+}
+
+/// Keeps offset in memory
+///
+/// Clones share the same offset
+#[derive(Clone, Debug, Default)]
+pub struct MemoryOffsetStore {
+    offset: Arc<Mutex<Option<Integer>>>,
+}
+
+impl MemoryOffsetStore {
+    /// Creates a new store
+    pub fn new() -> Self {
+        MemoryOffsetStore::default()
+    }
+
+    /// Returns a stored offset
+    pub fn get_offset(&self) -> Option<Integer> {
+        *self.offset.lock().unwrap()
+    }
+}
+
+impl OffsetStore for MemoryOffsetStore {
+    fn load(&self) -> Box<dyn Future<Item = Option<Integer>, Error = Error> + Send> {
+        Box::new(future::ok(self.get_offset()))
+    }
+
+    fn save(&self, offset: Integer) -> Box<dyn Future<Item = (), Error = Error> + Send> {
+        *self.offset.lock().unwrap() = Some(offset);
+        Box::new(future::ok(()))
+    }
+}
+
+/// Keeps offset in a file
+///
+/// File contains a decimal number, it is replaced atomically when offset is saved
+#[derive(Clone, Debug)]
+pub struct FileOffsetStore {
+    path: PathBuf,
+}
+
+impl FileOffsetStore {
+    /// Creates a new store
+    ///
+    /// # Arguments
+    ///
+    /// * path - Path to file, it is created on first save
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        FileOffsetStore { path: path.into() }
+    }
+}
+
+impl OffsetStore for FileOffsetStore {
+    fn load(&self) -> Box<dyn Future<Item = Option<Integer>, Error = Error> + Send> {
+        let path = self.path.clone();
+        Box::new(
+            blocking({
+                let path = path.clone();
+                move || match fs::read_to_string(&path) {
+                    Ok(data) => Ok(Some(data)),
+                    Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+                    Err(err) => Err(err),
+                }
+            })
+            .and_then(move |data| match data {
+                Some(data) => data.trim().parse().map(Some).map_err(|_| {
+                    OffsetStoreError {
+                        path: path.display().to_string(),
+                        reason: format!("{:?} is not an update identifier", data),
+                    }
+                    .into()
+                }),
+                None => Ok(None),
+            }),
+        )
+    }
+
+    fn save(&self, offset: Integer) -> Box<dyn Future<Item = (), Error = Error> + Send> {
+        let path = self.path.clone();
+        Box::new(blocking(move || {
+            // a partially written file must not be read after crash
+            let mut tmp_path = path.clone().into_os_string();
+            tmp_path.push(".tmp");
+            let mut file = fs::File::create(&tmp_path)?;
+            file.write_all(offset.to_string().as_bytes())?;
+            file.sync_all()?;
+            fs::rename(&tmp_path, &path)
+        }))
+    }
+}
+
+/// An error when loading offset from a file
+#[derive(Debug, failure::Fail)]
+#[fail(display = "Failed to load offset from {}: {}", path, reason)]
+pub struct OffsetStoreError {
+    path: String,
+    reason: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn memory_store() {
+        let store = MemoryOffsetStore::new();
+        assert_eq!(store.load().wait().unwrap(), None);
+        store.clone().save(1).wait().unwrap();
+        assert_eq!(store.load().wait().unwrap(), Some(1));
+        assert_eq!(store.get_offset(), Some(1));
+    }
+
+    #[test]
+    fn file_store() {
+        let path = env::temp_dir().join(format!("tgbot-offset-{}", std::process::id()));
+        let store = FileOffsetStore::new(&path);
+        assert_eq!(store.load().wait().unwrap(), None);
+        store.save(10).wait().unwrap();
+        store.save(11).wait().unwrap();
+        assert_eq!(store.load().wait().unwrap(), Some(11));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "11");
+        // file is written in a blocking section on a thread pool
+        let pool = tokio_threadpool::ThreadPool::new();
+        pool.spawn_handle(store.save(12)).wait().unwrap();
+        assert_eq!(pool.spawn_handle(store.load()).wait().unwrap(), Some(12));
+        fs::write(&path, "test").unwrap();
+        assert!(store.load().wait().is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
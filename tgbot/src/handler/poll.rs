@@ -1,13 +1,15 @@
 use crate::{
     api::{Api, ApiFuture},
+    handler::{CommitPolicy, OffsetStore},
     methods::GetUpdates,
     types::{AllowedUpdate, Integer, ResponseError, Update},
 };
 use failure::Error;
 use futures::{task, try_ready, Async, Future, Poll, Stream};
 use log::error;
+use rand::{thread_rng, Rng};
 use std::{
-    cmp::max,
+    cmp::{max, min},
     collections::{HashSet, VecDeque},
     mem,
     time::Duration,
@@ -17,11 +19,18 @@ use tokio_timer::sleep;
 const DEFAULT_LIMIT: Integer = 100;
 const DEFAULT_POLL_TIMEOUT: Duration = Duration::from_secs(10);
 const DEFAULT_ERROR_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_MAX_ERROR_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Called with a number of consecutive failures and the last error
+type FailureCallback = Box<dyn FnMut(usize, &Error) + Send>;
 
 enum State {
+    Loading(Box<dyn Future<Item = Option<Integer>, Error = Error> + Send>),
     BufferedResults(VecDeque<Update>),
     Running(ApiFuture<Vec<Update>>),
     Idling(tokio_timer::Delay),
+    Confirming(ApiFuture<Vec<Update>>),
+    Stopped,
 }
 
 /// Updates stream used for long polling
@@ -31,6 +40,13 @@ pub struct UpdatesStream {
     options: UpdatesStreamOptions,
     state: State,
     should_retry: bool,
+    is_stopping: bool,
+    offset_store: Option<Box<dyn OffsetStore>>,
+    commit_policy: CommitPolicy,
+    uncommitted: Option<Integer>,
+    commit: Option<Box<dyn Future<Item = (), Error = Error> + Send>>,
+    failures: usize,
+    on_failure: Option<FailureCallback>,
 }
 
 fn make_request(api: &Api, options: &UpdatesStreamOptions) -> ApiFuture<Vec<Update>> {
@@ -43,21 +59,37 @@ fn make_request(api: &Api, options: &UpdatesStreamOptions) -> ApiFuture<Vec<Upda
     )
 }
 
+/// Returns a delay before a next request after a number of consecutive failures
+///
+/// Delay is doubled after each failure up to a maximum,
+/// then it is randomized between a half and a full value, so bots do not retry at the same time
+fn get_backoff(options: &UpdatesStreamOptions, failures: usize) -> Duration {
+    let exponent = min(failures.saturating_sub(1), 31) as u32;
+    let delay = options
+        .error_timeout
+        .checked_mul(1 << exponent)
+        .map_or(options.max_error_timeout, |delay| min(delay, options.max_error_timeout));
+    let half = delay / 2;
+    let jitter = thread_rng().gen_range(0, half.as_nanos() as u64 + 1);
+    half + Duration::from_nanos(jitter)
+}
+
+/// Returns retry_after parameter of an error returned by Telegram
+fn get_retry_after(err: &Error) -> Option<Duration> {
+    err.downcast_ref::<ResponseError>()
+        .and_then(|err| err.parameters.as_ref())
+        .and_then(|parameters| parameters.retry_after)
+        .map(|count| Duration::from_secs(count as u64))
+}
+
 impl State {
-    fn switch_to_idle(&mut self, err: Error) {
+    fn switch_to_idle(&mut self, err: Error, error_timeout: Duration) {
         error!(
-            "An error has occurred while getting updates: {:?}\n{:?}",
+            "An error has occurred while getting updates (retrying in {:?}): {:?}\n{:?}",
+            error_timeout,
             err,
             err.backtrace()
         );
-        let error_timeout = err
-            .downcast::<ResponseError>()
-            .ok()
-            .and_then(|err| {
-                err.parameters
-                    .and_then(|parameters| parameters.retry_after.map(|count| Duration::from_secs(count as u64)))
-            })
-            .unwrap_or(DEFAULT_ERROR_TIMEOUT);
         mem::replace(self, State::Idling(sleep(error_timeout)));
     }
 
@@ -69,6 +101,22 @@ impl State {
     fn switch_to_buffered(&mut self, items: impl IntoIterator<Item = Update>) {
         mem::replace(self, State::BufferedResults(items.into_iter().collect()));
     }
+
+    fn switch_to_confirming(&mut self, api: &Api, options: &UpdatesStreamOptions) {
+        if options.offset == 0 {
+            *self = State::Stopped;
+        } else {
+            // updates are confirmed by offset, returned update is not confirmed and will be received again
+            *self = State::Confirming(
+                api.execute(
+                    GetUpdates::default()
+                        .offset(options.offset + 1)
+                        .limit(1)
+                        .timeout(Duration::from_secs(0)),
+                ),
+            );
+        }
+    }
 }
 
 impl Stream for UpdatesStream {
@@ -77,22 +125,61 @@ impl Stream for UpdatesStream {
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
         loop {
+            // received updates are returned after offset is saved,
+            // handled updates are not delayed by a slow store
+            let is_committed = self.poll_commit();
+            if !is_committed && self.commit_policy == CommitPolicy::AfterFetch {
+                return Ok(Async::NotReady);
+            }
             match &mut self.state {
+                State::Loading(load_fut) => match load_fut.poll() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Ok(Async::Ready(offset)) => {
+                        if let Some(offset) = offset {
+                            self.options.offset = offset;
+                        }
+                        self.state.switch_to_buffered(Vec::new())
+                    }
+                    Err(err) => {
+                        self.state = State::Stopped;
+                        return Err(err);
+                    }
+                },
                 State::BufferedResults(buffered) => {
                     if let Some(update) = buffered.pop_front() {
                         self.options.offset = max(self.options.offset, update.id);
+                        if self.commit_policy == CommitPolicy::AfterHandle {
+                            self.uncommitted = Some(self.options.offset);
+                        }
                         task::current().notify();
                         return Ok(Async::Ready(Some(update)));
+                    } else if self.is_stopping {
+                        self.state.switch_to_confirming(&self.api, &self.options);
                     } else {
                         self.state.switch_to_request(&self.api, &self.options);
                     }
                 }
+                State::Running(_) | State::Idling(_) if self.is_stopping => {
+                    self.state.switch_to_confirming(&self.api, &self.options)
+                }
                 State::Running(request_fut) => match request_fut.poll() {
                     Ok(Async::NotReady) => return Ok(Async::NotReady),
-                    Ok(Async::Ready(items)) => self.state.switch_to_buffered(items),
+                    Ok(Async::Ready(items)) => {
+                        self.failures = 0;
+                        if self.commit_policy == CommitPolicy::AfterFetch {
+                            self.uncommitted = items.iter().map(|update| update.id).max();
+                        }
+                        self.state.switch_to_buffered(items)
+                    }
                     Err(err) => {
+                        self.failures += 1;
+                        if let Some(ref mut on_failure) = self.on_failure {
+                            on_failure(self.failures, &err);
+                        }
                         if self.should_retry {
-                            self.state.switch_to_idle(err)
+                            let error_timeout =
+                                get_retry_after(&err).unwrap_or_else(|| get_backoff(&self.options, self.failures));
+                            self.state.switch_to_idle(err, error_timeout)
                         } else {
                             return Err(err);
                         }
@@ -103,6 +190,16 @@ impl Stream for UpdatesStream {
                     try_ready!(delay_fut.poll());
                     self.state.switch_to_request(&self.api, &self.options)
                 }
+                State::Confirming(request_fut) => match request_fut.poll() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Ok(Async::Ready(_)) => self.state = State::Stopped,
+                    Err(err) => {
+                        error!("Failed to confirm updates: {}", err);
+                        self.state = State::Stopped
+                    }
+                },
+                State::Stopped if is_committed => return Ok(Async::Ready(None)),
+                State::Stopped => return Ok(Async::NotReady),
             }
         }
     }
@@ -111,16 +208,30 @@ impl Stream for UpdatesStream {
 impl UpdatesStream {
     /// Creates a new updates stream
     pub fn new(api: Api) -> Self {
-        let options = UpdatesStreamOptions::default();
-        let state = State::Running(make_request(&api, &options));
         UpdatesStream {
             api,
-            options,
-            state,
+            options: UpdatesStreamOptions::default(),
+            // first request is sent on first poll, so options can be changed
+            state: State::BufferedResults(VecDeque::new()),
             should_retry: true,
+            is_stopping: false,
+            offset_store: None,
+            commit_policy: CommitPolicy::AfterHandle,
+            uncommitted: None,
+            commit: None,
+            failures: 0,
+            on_failure: None,
         }
     }
 
+    /// Stops fetching updates
+    ///
+    /// Buffered updates are still returned,
+    /// then offset of received updates is confirmed and stream ends
+    pub(crate) fn stop(&mut self) {
+        self.is_stopping = true;
+    }
+
     /// Whether or not a request should be retried if it has resolved into an error.
     ///
     /// Default value is `true`
@@ -134,6 +245,57 @@ impl UpdatesStream {
         self.options = options;
         self
     }
+
+    /// Sets a function called when getUpdates request fails
+    ///
+    /// Function receives a number of consecutive failures and an error,
+    /// the number is reset after a successful request.
+    pub fn on_failure<F>(mut self, on_failure: F) -> Self
+    where
+        F: FnMut(usize, &Error) + Send + 'static,
+    {
+        self.on_failure = Some(Box::new(on_failure));
+        self
+    }
+
+    /// Saves offset of received updates to a store
+    ///
+    /// Polling is resumed from a stored offset,
+    /// it takes precedence over [UpdatesStreamOptions::offset()](struct.UpdatesStreamOptions.html#method.offset).
+    /// Stream fails when offset can not be loaded, save errors are logged.
+    pub fn offset_store<S: OffsetStore + 'static>(mut self, offset_store: S) -> Self {
+        self.state = State::Loading(offset_store.load());
+        self.offset_store = Some(Box::new(offset_store));
+        self
+    }
+
+    /// When offset is saved to a store
+    ///
+    /// Defaults to [CommitPolicy::AfterHandle](enum.CommitPolicy.html#variant.AfterHandle)
+    pub fn commit_policy(mut self, commit_policy: CommitPolicy) -> Self {
+        self.commit_policy = commit_policy;
+        self
+    }
+
+    /// Saves the last uncommitted offset, if any
+    ///
+    /// Returns true when there is nothing to save
+    fn poll_commit(&mut self) -> bool {
+        loop {
+            if let Some(commit_fut) = &mut self.commit {
+                match commit_fut.poll() {
+                    Ok(Async::NotReady) => return false,
+                    Ok(Async::Ready(())) => {}
+                    Err(err) => error!("Failed to save offset: {}", err),
+                }
+                self.commit = None;
+            }
+            match (self.uncommitted.take(), &self.offset_store) {
+                (Some(offset), Some(store)) => self.commit = Some(store.save(offset)),
+                _ => return true,
+            }
+        }
+    }
 }
 
 impl From<Api> for UpdatesStream {
@@ -151,10 +313,21 @@ pub struct UpdatesStreamOptions {
     limit: Integer,
     poll_timeout: Duration,
     error_timeout: Duration,
+    max_error_timeout: Duration,
     allowed_updates: HashSet<AllowedUpdate>,
 }
 
 impl UpdatesStreamOptions {
+    /// Identifier of the last received update
+    ///
+    /// Updates with greater identifiers are requested
+    ///
+    /// Defaults to 0
+    pub fn offset(mut self, offset: Integer) -> Self {
+        self.offset = offset;
+        self
+    }
+
     /// Limits the number of updates to be retrieved
     ///
     /// Values between 1—100 are accepted
@@ -179,12 +352,24 @@ impl UpdatesStreamOptions {
 
     /// Timeout in seconds when an error has occurred
     ///
+    /// Timeout is doubled after each consecutive error up to
+    /// [max_error_timeout](#method.max_error_timeout) and randomized down to a half of its value.
+    /// `retry_after` returned by Telegram is used instead when present.
+    ///
     /// Defaults to 5
     pub fn error_timeout(mut self, error_timeout: u64) -> Self {
         self.error_timeout = Duration::from_secs(error_timeout);
         self
     }
 
+    /// Maximum timeout in seconds when errors occur repeatedly
+    ///
+    /// Defaults to 60
+    pub fn max_error_timeout(mut self, max_error_timeout: u64) -> Self {
+        self.max_error_timeout = Duration::from_secs(max_error_timeout);
+        self
+    }
+
     /// Adds a type of updates you want your bot to receive
     pub fn allowed_update(mut self, allowed_update: AllowedUpdate) -> Self {
         self.allowed_updates.insert(allowed_update);
@@ -199,7 +384,25 @@ impl Default for UpdatesStreamOptions {
             limit: DEFAULT_LIMIT,
             poll_timeout: DEFAULT_POLL_TIMEOUT,
             error_timeout: DEFAULT_ERROR_TIMEOUT,
+            max_error_timeout: DEFAULT_MAX_ERROR_TIMEOUT,
             allowed_updates: HashSet::new(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff() {
+        let options = UpdatesStreamOptions::default().error_timeout(4).max_error_timeout(10);
+        for &(failures, expected) in &[(1, 4), (2, 8), (3, 10), (100, 10)] {
+            let expected = Duration::from_secs(expected);
+            let delay = get_backoff(&options, failures);
+            assert!(delay >= expected / 2 && delay <= expected, "{:?} {:?}", delay, expected);
+        }
+        let options = options.error_timeout(0);
+        assert_eq!(get_backoff(&options, 1), Duration::from_secs(0));
+    }
+}
@@ -0,0 +1,253 @@
+use crate::{
+    api::Api,
+    methods::{DeleteWebhook, GetWebhookInfo, SetWebhook},
+    types::{AllowedUpdate, InputFile, Integer, WebhookInfo},
+};
+use futures::{
+    future::{self, Either},
+    Future, Stream,
+};
+use std::{
+    collections::HashSet,
+    time::{Duration, Instant},
+};
+use tokio_timer::Interval;
+
+const DEFAULT_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Registers a webhook when updates handling starts
+///
+/// Webhook info is checked periodically, delivery errors reported by Telegram are logged.
+/// See [UpdateMethod::register()](struct.UpdateMethod.html#method.register)
+pub struct WebhookRegistration {
+    api: Api,
+    url: String,
+    certificate: Option<InputFile>,
+    ip_address: Option<String>,
+    max_connections: Option<Integer>,
+    allowed_updates: Option<HashSet<AllowedUpdate>>,
+    check_interval: Duration,
+    delete_on_shutdown: bool,
+}
+
+impl WebhookRegistration {
+    /// Creates a new registration
+    ///
+    /// # Arguments
+    ///
+    /// * api - Api to call setWebhook with
+    /// * url - Public HTTPS URL of webhook
+    pub fn new<S: Into<String>>(api: Api, url: S) -> Self {
+        WebhookRegistration {
+            api,
+            url: url.into(),
+            certificate: None,
+            ip_address: None,
+            max_connections: None,
+            allowed_updates: None,
+            check_interval: DEFAULT_CHECK_INTERVAL,
+            delete_on_shutdown: false,
+        }
+    }
+
+    /// Public key certificate, see [SetWebhook::certificate()](methods/struct.SetWebhook.html#method.certificate)
+    pub fn certificate<C: Into<InputFile>>(mut self, certificate: C) -> Self {
+        self.certificate = Some(certificate.into());
+        self
+    }
+
+    /// Fixed IP address to send webhook requests to
+    pub fn ip_address<S: Into<String>>(mut self, ip_address: S) -> Self {
+        self.ip_address = Some(ip_address.into());
+        self
+    }
+
+    /// Maximum allowed number of simultaneous HTTPS connections to the webhook, 1-100
+    pub fn max_connections(mut self, max_connections: Integer) -> Self {
+        self.max_connections = Some(max_connections);
+        self
+    }
+
+    /// Types of updates you want your bot to receive
+    pub fn allowed_updates(mut self, allowed_updates: HashSet<AllowedUpdate>) -> Self {
+        self.allowed_updates = Some(allowed_updates);
+        self
+    }
+
+    /// Adds a type of updates you want your bot to receive
+    pub fn add_allowed_update(mut self, allowed_update: AllowedUpdate) -> Self {
+        self.allowed_updates
+            .get_or_insert_with(HashSet::new)
+            .insert(allowed_update);
+        self
+    }
+
+    /// How often to check webhook info, defaults to 60 seconds
+    pub fn check_interval(mut self, check_interval: Duration) -> Self {
+        self.check_interval = check_interval;
+        self
+    }
+
+    /// Delete webhook when server is stopped by a shutdown signal, defaults to false
+    ///
+    /// See [UpdateMethod::shutdown_signal()](struct.UpdateMethod.html#method.shutdown_signal)
+    pub fn delete_on_shutdown(mut self, delete_on_shutdown: bool) -> Self {
+        self.delete_on_shutdown = delete_on_shutdown;
+        self
+    }
+
+    /// Sets webhook, then runs a server and checks webhook info until the server stops
+    ///
+    /// Resolves with true when server is stopped by shutdown signal, false when server fails.
+    /// Webhook is deleted only in the first case.
+    /// Server is not started and an error is returned when webhook can not be set
+    pub(super) fn run<F>(self, secret_token: Option<String>, server: F) -> impl Future<Item = bool, Error = ()>
+    where
+        F: Future<Item = (), Error = ()> + Send + 'static,
+    {
+        let WebhookRegistration {
+            api,
+            url,
+            certificate,
+            ip_address,
+            max_connections,
+            allowed_updates,
+            check_interval,
+            delete_on_shutdown,
+        } = self;
+        let mut method = SetWebhook::new(url.as_str());
+        if let Some(certificate) = certificate {
+            method = method.certificate(certificate);
+        }
+        if let Some(ip_address) = ip_address {
+            method = method.ip_address(ip_address);
+        }
+        if let Some(max_connections) = max_connections {
+            method = method.max_connections(max_connections);
+        }
+        if let Some(allowed_updates) = allowed_updates {
+            method = method.allowed_updates(allowed_updates);
+        }
+        if let Some(secret_token) = secret_token {
+            method = method.secret_token(secret_token);
+        }
+        let info_api = api.clone();
+        api.execute(method)
+            .and_then(move |_| info_api.execute(GetWebhookInfo))
+            .then(move |result| match result {
+                Ok(info) => {
+                    if info.url != url {
+                        log::warn!("Webhook URL is {:?} instead of {:?}", info.url, url);
+                    }
+                    log::info!("Webhook is set to {}", url);
+                    let last_error_date = log_last_error(&info, None);
+                    let monitor_api = api.clone();
+                    let monitor = Interval::new(Instant::now() + check_interval, check_interval)
+                        .map_err(|err| log::error!("Webhook info timer error: {}", err))
+                        .fold(last_error_date, move |last_error_date, _| {
+                            monitor_api.execute(GetWebhookInfo).then(move |result| {
+                                Ok(match result {
+                                    Ok(info) => log_last_error(&info, last_error_date),
+                                    Err(err) => {
+                                        log::warn!("Failed to get webhook info: {}", err);
+                                        last_error_date
+                                    }
+                                })
+                            })
+                        });
+                    Either::A(
+                        server
+                            .select2(monitor)
+                            .then(|result| match result {
+                                Ok(Either::A(_)) => Either::A(future::ok(true)),
+                                Err(Either::A(_)) => Either::A(future::ok(false)),
+                                // keep serving when timer fails
+                                Ok(Either::B((_, server))) | Err(Either::B((_, server))) => {
+                                    Either::B(server.then(|result| Ok(result.is_ok())))
+                                }
+                            })
+                            .and_then(move |is_graceful| {
+                                if is_graceful && delete_on_shutdown {
+                                    Either::A(api.execute(DeleteWebhook).then(|result| {
+                                        match result {
+                                            Ok(_) => log::info!("Webhook is deleted"),
+                                            Err(err) => log::error!("Failed to delete webhook: {}", err),
+                                        }
+                                        Ok(true)
+                                    }))
+                                } else {
+                                    Either::B(future::ok(is_graceful))
+                                }
+                            }),
+                    )
+                }
+                Err(err) => {
+                    log::error!("Failed to set webhook: {}", err);
+                    Either::B(future::err(()))
+                }
+            })
+    }
+}
+
+/// Logs a delivery error unless it has been logged already
+///
+/// Returns a date of the last error
+fn log_last_error(info: &WebhookInfo, logged_date: Option<Integer>) -> Option<Integer> {
+    if info.last_error_date != logged_date {
+        if let Some(ref message) = info.last_error_message {
+            log::warn!(
+                "Telegram failed to deliver an update to webhook: {} ({} pending updates)",
+                message,
+                info.pending_update_count
+            );
+        }
+    }
+    info.last_error_date
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{executor::Executor, request::Request};
+    use failure::Error;
+    use std::sync::{Arc, Mutex};
+    use tokio::runtime::current_thread::block_on_all;
+
+    struct ExecutorMock {
+        calls: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Executor for ExecutorMock {
+        fn execute(&self, req: Request) -> Box<dyn Future<Item = Vec<u8>, Error = Error> + Send> {
+            let method = req.url.rsplit('/').next().unwrap().to_string();
+            let response: &[u8] = if method == "getWebhookInfo" {
+                br#"{"ok": true, "result": {
+                    "url": "https://example.com/updates",
+                    "has_custom_certificate": false,
+                    "pending_update_count": 0
+                }}"#
+            } else {
+                br#"{"ok": true, "result": true}"#
+            };
+            self.calls.lock().unwrap().push(method);
+            Box::new(future::ok(response.to_vec()))
+        }
+    }
+
+    #[test]
+    fn delete_on_shutdown() {
+        for &(server_result, is_graceful) in &[(Ok(()), true), (Err(()), false)] {
+            let calls = Arc::new(Mutex::new(Vec::new()));
+            let api = Api::with_executor("token", ExecutorMock { calls: calls.clone() });
+            let registration = WebhookRegistration::new(api, "https://example.com/updates").delete_on_shutdown(true);
+            let result = block_on_all(registration.run(None, future::result(server_result)));
+            assert_eq!(result, Ok(is_graceful));
+            let mut expected = vec!["setWebhook", "getWebhookInfo"];
+            // webhook is not deleted when server fails
+            if is_graceful {
+                expected.push("deleteWebhook");
+            }
+            assert_eq!(*calls.lock().unwrap(), expected);
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use crate::methods::Method;
+use failure::Error;
+use futures::sync::oneshot;
+
+/// Sends a method in response to a webhook request
+///
+/// Saves a request to Telegram, but a result of the method is not available.
+/// See [UpdateHandler::handle_with_reply()](trait.UpdateHandler.html#method.handle_with_reply)
+#[derive(Debug)]
+pub struct WebhookReply {
+    sender: oneshot::Sender<Vec<u8>>,
+}
+
+impl WebhookReply {
+    pub(super) fn new() -> (Self, oneshot::Receiver<Vec<u8>>) {
+        let (sender, receiver) = oneshot::channel();
+        (WebhookReply { sender }, receiver)
+    }
+
+    /// Sends a method in a webhook response
+    ///
+    /// Files can not be uploaded, file_id or URL must be used instead.
+    /// Fails with [WebhookReplyError](enum.WebhookReplyError.html)
+    /// when response has been sent already, so the method must be executed using Api.
+    pub fn send<M: Method>(self, method: M) -> Result<(), Error> {
+        let body = method.into_request()?.into_webhook_reply()?;
+        self.sender.send(body).map_err(|_| WebhookReplyError::Expired.into())
+    }
+
+    /// Returns true when response has been sent already because reply timeout is expired
+    pub fn is_expired(&self) -> bool {
+        self.sender.is_canceled()
+    }
+}
+
+/// An error when sending a webhook reply
+#[derive(Debug, failure::Fail)]
+pub enum WebhookReplyError {
+    /// Method contains a file to upload
+    #[fail(display = "Can not upload a file in a webhook response")]
+    Upload,
+    /// Method body is not a JSON object
+    #[fail(display = "Can not convert a method into a webhook response: body is not a JSON object")]
+    InvalidBody,
+    /// Response has been sent already
+    #[fail(display = "Webhook response has been sent already")]
+    Expired,
+}
@@ -0,0 +1,118 @@
+#[cfg(feature = "polling")]
+use crate::handler::UpdatesStream;
+#[cfg(feature = "webhook")]
+use crate::handler::WebhookReply;
+use crate::{handler::UpdateHandler, types::Update};
+#[cfg(feature = "polling")]
+use failure::Error;
+use futures::{future, Future};
+#[cfg(feature = "polling")]
+use futures::{Async, Poll, Stream};
+use std::{
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+use tokio_timer::Timeout;
+
+pub(super) type BoxedFuture = Box<dyn Future<Item = (), Error = ()> + Send>;
+
+/// Resolves when updates handling should be stopped
+pub(super) type ShutdownSignal = BoxedFuture;
+
+/// An update handler which can be drained after it has been passed to an update source
+pub(super) struct SharedHandler<H>(Arc<Mutex<H>>);
+
+impl<H> Clone for SharedHandler<H> {
+    fn clone(&self) -> Self {
+        SharedHandler(self.0.clone())
+    }
+}
+
+impl<H: UpdateHandler> SharedHandler<H> {
+    pub(super) fn new(handler: H) -> Self {
+        SharedHandler(Arc::new(Mutex::new(handler)))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, H> {
+        // a panic in handler must not prevent other updates from being handled
+        self.0.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl<H: UpdateHandler> UpdateHandler for SharedHandler<H> {
+    fn handle(&mut self, update: Update) {
+        self.lock().handle(update)
+    }
+
+    #[cfg(feature = "webhook")]
+    fn handle_with_reply(&mut self, update: Update, reply: WebhookReply) {
+        self.lock().handle_with_reply(update, reply)
+    }
+
+    fn drain(&mut self) -> BoxedFuture {
+        self.lock().drain()
+    }
+}
+
+/// Waits until received updates are processed, but no longer than a given timeout
+///
+/// # Arguments
+///
+/// * processed - Resolves when all received updates are passed to handler
+/// * handler - Handler to drain
+/// * timeout - Shutdown timeout
+pub(super) fn drain<F, H>(
+    processed: F,
+    mut handler: SharedHandler<H>,
+    timeout: Duration,
+) -> impl Future<Item = (), Error = ()>
+where
+    F: Future<Item = (), Error = ()>,
+    H: UpdateHandler,
+{
+    Timeout::new(processed.and_then(move |()| handler.drain()), timeout).then(|result| {
+        match result {
+            Ok(()) => log::info!("Updates handling has been stopped"),
+            Err(ref err) if err.is_elapsed() => {
+                log::warn!("Shutdown timeout has expired, some updates may not be processed")
+            }
+            Err(err) => log::error!("Failed to stop updates handling: {:?}", err),
+        }
+        future::ok(())
+    })
+}
+
+/// Updates stream which stops fetching updates when a signal resolves
+#[cfg(feature = "polling")]
+pub(super) struct StoppableStream {
+    stream: UpdatesStream,
+    signal: Option<ShutdownSignal>,
+}
+
+#[cfg(feature = "polling")]
+impl StoppableStream {
+    pub(super) fn new(stream: UpdatesStream, signal: Option<ShutdownSignal>) -> Self {
+        StoppableStream { stream, signal }
+    }
+}
+
+#[cfg(feature = "polling")]
+impl Stream for StoppableStream {
+    type Item = Update;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let is_stopped = match self.signal {
+            Some(ref mut signal) => match signal.poll() {
+                Ok(Async::NotReady) => false,
+                Ok(Async::Ready(())) | Err(()) => true,
+            },
+            None => false,
+        };
+        if is_stopped {
+            self.signal = None;
+            self.stream.stop();
+        }
+        self.stream.poll()
+    }
+}
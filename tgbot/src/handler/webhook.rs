@@ -1,21 +1,70 @@
-use crate::{types::Update, Never, UpdateHandler};
+use crate::{handler::WebhookReply, types::Update, Never, UpdateHandler};
 use futures::{
-    future::{ok, Either},
-    Future, Sink, Stream,
+    future::{self, ok, Either},
+    sync::oneshot,
+    AsyncSink, Future, Sink, Stream,
 };
 use hyper::{
-    header::{HeaderValue, ALLOW},
+    header::{HeaderMap, HeaderValue, ALLOW, CONTENT_TYPE},
     service::{MakeService, Service},
     Body, Error, Method, Request, Response, StatusCode,
 };
 use lazy_queue::sync::bounded::LazyQueue;
+use serde_json::json;
+use std::{
+    cmp::max,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 use tokio_executor::spawn;
+use tokio_timer::Timeout;
+
+const SECRET_TOKEN_HEADER: &str = "X-Telegram-Bot-Api-Secret-Token";
+const DEFAULT_QUEUE_SIZE: usize = 10;
+const HEALTH_PATH: &str = "/healthz";
+const READY_PATH: &str = "/readyz";
+const STATS_PATH: &str = "/stats";
+
+/// An update with a reply slot when replies are enabled
+type QueueItem = (Update, Option<WebhookReply>);
+
+type QueueHandler = Box<dyn FnMut(QueueItem) -> Result<(), Never> + Send>;
+
+/// What to do with a webhook request when updates queue is full
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum QueueOverflow {
+    /// Wait for a free slot, response is sent after update is queued
+    Wait,
+    /// Respond with 503 Service Unavailable, Telegram delivers update again later
+    Reject,
+}
+
+/// Counters of queued updates
+#[derive(Debug, Default)]
+struct QueueStats {
+    depth: AtomicUsize,
+    processed: AtomicUsize,
+    failed: AtomicUsize,
+    rejected: AtomicUsize,
+}
 
 #[doc(hidden)]
 pub struct WebhookServiceFactory {
     path: String,
-    queue: LazyQueue<Update>,
-    processor: Option<Box<dyn Future<Item = (), Error = ()> + Send>>,
+    secret_token: Option<String>,
+    reply_timeout: Option<Duration>,
+    queue_size: usize,
+    queue_overflow: QueueOverflow,
+    status_routes: bool,
+    stats: Arc<QueueStats>,
+    queue: Option<LazyQueue<QueueItem>>,
+    handler: Option<QueueHandler>,
+    processed_tx: Option<oneshot::Sender<()>>,
+    processed: Option<oneshot::Receiver<()>>,
 }
 
 impl WebhookServiceFactory {
@@ -25,20 +74,114 @@ impl WebhookServiceFactory {
         S: Into<String>,
         H: UpdateHandler + Send + Sync + 'static,
     {
-        const QUEUE_SIZE: usize = 10;
-        let (queue, processor) = LazyQueue::new(
-            move |update| {
-                update_handler.handle(update);
-                Ok::<_, Never>(())
-            },
-            QUEUE_SIZE,
-        );
+        let stats = Arc::new(QueueStats::default());
+        let handler_stats = stats.clone();
+        let handler = move |(update, reply): QueueItem| {
+            // a panic must not stop the queue
+            let result = panic::catch_unwind(AssertUnwindSafe(|| match reply {
+                Some(reply) => update_handler.handle_with_reply(update, reply),
+                None => update_handler.handle(update),
+            }));
+            handler_stats.depth.fetch_sub(1, Ordering::SeqCst);
+            match result {
+                Ok(()) => handler_stats.processed.fetch_add(1, Ordering::SeqCst),
+                Err(_) => {
+                    log::error!("Update handler has panicked");
+                    handler_stats.failed.fetch_add(1, Ordering::SeqCst)
+                }
+            };
+            Ok(())
+        };
+        let (processed_tx, processed_rx) = oneshot::channel();
         WebhookServiceFactory {
             path: path.into(),
-            queue,
-            processor: Some(Box::new(processor.map_err(|e| log::error!("Processing error: {}", e)))),
+            secret_token: None,
+            reply_timeout: None,
+            queue_size: DEFAULT_QUEUE_SIZE,
+            queue_overflow: QueueOverflow::Wait,
+            status_routes: false,
+            stats,
+            queue: None,
+            handler: Some(Box::new(handler)),
+            processed_tx: Some(processed_tx),
+            processed: Some(processed_rx),
         }
     }
+
+    /// Returns a queue, it is created and processing starts on first call
+    fn get_queue(&mut self) -> LazyQueue<QueueItem> {
+        if let Some(ref queue) = self.queue {
+            return queue.clone();
+        }
+        let handler = self.handler.take().expect("Queue handler is already taken");
+        let processed_tx = self.processed_tx.take();
+        let (queue, processor) = LazyQueue::new(handler, self.queue_size);
+        spawn(
+            processor
+                .map_err(|e| log::error!("Processing error: {}", e))
+                .then(move |result| {
+                    if let Some(processed_tx) = processed_tx {
+                        let _ = processed_tx.send(());
+                    }
+                    result
+                }),
+        );
+        self.queue = Some(queue.clone());
+        queue
+    }
+
+    /// Returns a future which resolves when the queue is closed and all updates are passed to handler
+    ///
+    /// Queue is closed when factory and all services are dropped
+    pub(super) fn processed(&mut self) -> Box<dyn Future<Item = (), Error = ()> + Send> {
+        match self.processed.take() {
+            // queue is not created when there were no connections
+            Some(processed) => Box::new(processed.then(|_| Ok(()))),
+            None => Box::new(ok(())),
+        }
+    }
+
+    /// Rejects requests without a matching `X-Telegram-Bot-Api-Secret-Token` header
+    ///
+    /// Use the same value in [SetWebhook::secret_token](methods/struct.SetWebhook.html#method.secret_token)
+    pub fn secret_token<S: Into<String>>(mut self, secret_token: S) -> Self {
+        self.secret_token = Some(secret_token.into());
+        self
+    }
+
+    /// Allows update handler to reply with a method in a webhook response
+    ///
+    /// Response is empty when handler does not reply within a given timeout,
+    /// see [UpdateHandler::handle_with_reply()](trait.UpdateHandler.html#method.handle_with_reply)
+    pub fn reply_timeout(mut self, reply_timeout: Duration) -> Self {
+        self.reply_timeout = Some(reply_timeout);
+        self
+    }
+
+    /// Maximum number of updates waiting for handler, defaults to 10
+    ///
+    /// Zero is replaced with 1
+    pub fn queue_size(mut self, queue_size: usize) -> Self {
+        self.queue_size = max(queue_size, 1);
+        self
+    }
+
+    /// What to do when queue is full, defaults to [QueueOverflow::Wait](enum.QueueOverflow.html#variant.Wait)
+    pub fn queue_overflow(mut self, queue_overflow: QueueOverflow) -> Self {
+        self.queue_overflow = queue_overflow;
+        self
+    }
+
+    /// Enables `GET /healthz`, `GET /readyz` and `GET /stats` routes, disabled by default
+    ///
+    /// `/healthz` always responds with 200,
+    /// `/readyz` responds with 503 when queue is full,
+    /// `/stats` returns queue size and depth, numbers of processed, failed and rejected updates as JSON.
+    /// Failed updates are those that caused handler to panic.
+    pub fn status_routes(mut self, status_routes: bool) -> Self {
+        self.status_routes = status_routes;
+        self
+    }
 }
 
 impl<Ctx> MakeService<Ctx> for WebhookServiceFactory {
@@ -50,40 +193,161 @@ impl<Ctx> MakeService<Ctx> for WebhookServiceFactory {
     type MakeError = Never;
 
     fn make_service(&mut self, _ctx: Ctx) -> Self::Future {
-        let path = self.path.clone();
-        let queue = self.queue.clone();
-        if let Some(fut) = self.processor.take() {
-            spawn(fut);
-        }
-        Box::new(ok(WebhookService { path, queue }))
+        Box::new(ok(WebhookService {
+            path: self.path.clone(),
+            secret_token: self.secret_token.clone(),
+            reply_timeout: self.reply_timeout,
+            queue_size: self.queue_size,
+            queue_overflow: self.queue_overflow,
+            status_routes: self.status_routes,
+            stats: self.stats.clone(),
+            queue: self.get_queue(),
+        }))
     }
 }
 
 #[doc(hidden)]
 pub struct WebhookService {
     path: String,
-    queue: LazyQueue<Update>,
+    secret_token: Option<String>,
+    reply_timeout: Option<Duration>,
+    queue_size: usize,
+    queue_overflow: QueueOverflow,
+    status_routes: bool,
+    stats: Arc<QueueStats>,
+    queue: LazyQueue<QueueItem>,
+}
+
+impl WebhookService {
+    fn is_authorized(&self, headers: &HeaderMap) -> bool {
+        match self.secret_token {
+            Some(ref expected) => match headers.get(SECRET_TOKEN_HEADER) {
+                Some(actual) => constant_time_eq(actual.as_bytes(), expected.as_bytes()),
+                None => false,
+            },
+            None => true,
+        }
+    }
+
+    fn get_status_response(&self, request: &Request<Body>) -> Option<Response<Body>> {
+        if !self.status_routes || request.method() != Method::GET {
+            return None;
+        }
+        let depth = self.stats.depth.load(Ordering::SeqCst);
+        let (status, body) = match request.uri().path() {
+            HEALTH_PATH => (StatusCode::OK, Body::from("ok")),
+            READY_PATH if depth < self.queue_size => (StatusCode::OK, Body::from("ok")),
+            READY_PATH => (StatusCode::SERVICE_UNAVAILABLE, Body::from("queue is full")),
+            STATS_PATH => (
+                StatusCode::OK,
+                Body::from(
+                    json!({
+                        "queue_size": self.queue_size,
+                        "queue_depth": depth,
+                        "processed": self.stats.processed.load(Ordering::SeqCst),
+                        "failed": self.stats.failed.load(Ordering::SeqCst),
+                        "rejected": self.stats.rejected.load(Ordering::SeqCst),
+                    })
+                    .to_string(),
+                ),
+            ),
+            _ => return None,
+        };
+        Some(
+            Response::builder()
+                .status(status)
+                .body(body)
+                .expect("Can't construct a status response"),
+        )
+    }
+}
+
+/// Compares two byte strings in time which depends on length only
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Resolves into false when queue is full and update is rejected
+fn enqueue(
+    queue: LazyQueue<QueueItem>,
+    item: QueueItem,
+    queue_overflow: QueueOverflow,
+) -> impl Future<Item = bool, Error = ()> {
+    match queue_overflow {
+        QueueOverflow::Wait => Either::A(queue.send(item).then(|res| res.map(|_| true).map_err(|_| ()))),
+        QueueOverflow::Reject => Either::B(future::lazy(move || {
+            let mut queue = queue;
+            match queue.start_send(item) {
+                Ok(AsyncSink::Ready) => Either::A(queue.flush().then(|res| res.map(|_| true).map_err(|_| ()))),
+                Ok(AsyncSink::NotReady(_)) => Either::B(ok(false)),
+                Err(_) => Either::B(future::err(())),
+            }
+        })),
+    }
 }
 
 fn put_on_a_queue(
     request: Request<Body>,
-    queue: impl Sink<SinkItem = Update>,
+    queue: LazyQueue<QueueItem>,
+    queue_overflow: QueueOverflow,
+    stats: Arc<QueueStats>,
+    reply_timeout: Option<Duration>,
 ) -> impl Future<Item = Response<Body>, Error = Error> {
+    // handler has to reply before Telegram gives up waiting for a response
+    let reply_deadline = reply_timeout.map(|timeout| Instant::now() + timeout);
     request
         .into_body()
         .concat2()
         .and_then(move |body| match serde_json::from_slice(&body) {
-            Ok(update) => Either::A(queue.send(update).then(|res| {
-                if res.is_err() {
-                    log::warn!("The receiving end has been dropped");
-                    Ok(Response::builder()
-                        .status(StatusCode::INTERNAL_SERVER_ERROR)
-                        .body(Body::empty())
-                        .expect("Can't construct an INTERNAL_SERVER_ERROR response"))
-                } else {
-                    Ok(Response::new(Body::empty()))
-                }
-            })),
+            Ok(update) => {
+                let (reply, receiver) = match reply_deadline {
+                    Some(deadline) => {
+                        let (reply, receiver) = WebhookReply::new();
+                        (Some(reply), Some(Timeout::new_at(receiver, deadline)))
+                    }
+                    None => (None, None),
+                };
+                // counted before sending, so handler never decrements it first
+                stats.depth.fetch_add(1, Ordering::SeqCst);
+                Either::A(enqueue(queue, (update, reply), queue_overflow).then(move |res| {
+                    match res {
+                        Ok(true) => {}
+                        Ok(false) => {
+                            stats.depth.fetch_sub(1, Ordering::SeqCst);
+                            stats.rejected.fetch_add(1, Ordering::SeqCst);
+                            log::warn!("Rejected an update because queue is full");
+                            return Either::A(ok(Response::builder()
+                                .status(StatusCode::SERVICE_UNAVAILABLE)
+                                .body(Body::empty())
+                                .expect("Can't construct a SERVICE_UNAVAILABLE response")));
+                        }
+                        Err(()) => {
+                            stats.depth.fetch_sub(1, Ordering::SeqCst);
+                            log::warn!("The receiving end has been dropped");
+                            return Either::A(ok(Response::builder()
+                                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                                .body(Body::empty())
+                                .expect("Can't construct an INTERNAL_SERVER_ERROR response")));
+                        }
+                    }
+                    match receiver {
+                        // reply is dropped or timeout is expired when there is no method
+                        Some(receiver) => Either::B(receiver.then(|res| {
+                            Ok(match res {
+                                Ok(method) => Response::builder()
+                                    .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+                                    .body(Body::from(method))
+                                    .expect("Can't construct a reply response"),
+                                Err(_) => Response::new(Body::empty()),
+                            })
+                        })),
+                        None => Either::A(ok(Response::new(Body::empty()))),
+                    }
+                }))
+            }
             Err(err) => Either::B(ok(Response::builder()
                 .status(StatusCode::BAD_REQUEST)
                 .body(Body::from(err.to_string()))
@@ -99,14 +363,28 @@ impl Service for WebhookService {
 
     fn call(&mut self, req: Request<Self::ReqBody>) -> Self::Future {
         if let Method::POST = *req.method() {
-            if req.uri().path() == self.path {
-                Box::new(put_on_a_queue(req, self.queue.clone()))
-            } else {
+            if req.uri().path() != self.path {
                 Box::new(ok(Response::builder()
                     .status(StatusCode::NOT_FOUND)
                     .body(Body::empty())
                     .expect("Can't construct a NOT_FOUND response")))
+            } else if !self.is_authorized(req.headers()) {
+                log::warn!("Rejected a webhook request with invalid secret token");
+                Box::new(ok(Response::builder()
+                    .status(StatusCode::UNAUTHORIZED)
+                    .body(Body::empty())
+                    .expect("Can't construct an UNAUTHORIZED response")))
+            } else {
+                Box::new(put_on_a_queue(
+                    req,
+                    self.queue.clone(),
+                    self.queue_overflow,
+                    self.stats.clone(),
+                    self.reply_timeout,
+                ))
             }
+        } else if let Some(response) = self.get_status_response(&req) {
+            Box::new(ok(response))
         } else {
             Box::new(ok(Response::builder()
                 .status(StatusCode::METHOD_NOT_ALLOWED)
@@ -116,3 +394,85 @@ impl Service for WebhookService {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update() -> Update {
+        serde_json::from_value(json!({
+            "update_id": 1,
+            "message": {
+                "message_id": 1,
+                "date": 0,
+                "from": {"id": 1, "is_bot": false, "first_name": "test"},
+                "chat": {"id": 1, "type": "private", "first_name": "test"},
+                "text": "test"
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn enqueue_reject() {
+        // processor is not running, so queue is never drained
+        let (queue, _processor) = LazyQueue::new(|_: QueueItem| Ok::<_, Never>(()), 1);
+        assert!(enqueue(queue.clone(), (update(), None), QueueOverflow::Reject)
+            .wait()
+            .unwrap());
+        assert!(!enqueue(queue, (update(), None), QueueOverflow::Reject).wait().unwrap());
+    }
+
+    #[test]
+    fn queue_size() {
+        struct Handler;
+
+        impl UpdateHandler for Handler {
+            fn handle(&mut self, _update: Update) {}
+        }
+
+        let factory = WebhookServiceFactory::new("/", Handler);
+        assert_eq!(factory.queue_size, DEFAULT_QUEUE_SIZE);
+        assert_eq!(factory.queue_size(0).queue_size, 1);
+    }
+
+    fn get(service: &mut WebhookService, path: &str) -> (StatusCode, String) {
+        let req = Request::get(path).body(Body::empty()).unwrap();
+        let res = service.call(req).wait().unwrap();
+        let status = res.status();
+        let body = res.into_body().concat2().wait().unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[test]
+    fn status_routes() {
+        let (queue, _processor) = LazyQueue::new(|_: QueueItem| Ok::<_, Never>(()), 1);
+        let mut service = WebhookService {
+            path: String::from("/"),
+            secret_token: None,
+            reply_timeout: None,
+            queue_size: 1,
+            queue_overflow: QueueOverflow::Reject,
+            status_routes: false,
+            stats: Arc::new(QueueStats::default()),
+            queue,
+        };
+        assert_eq!(get(&mut service, "/healthz").0, StatusCode::METHOD_NOT_ALLOWED);
+
+        service.status_routes = true;
+        assert_eq!(get(&mut service, "/readyz"), (StatusCode::OK, String::from("ok")));
+        service.stats.depth.store(1, Ordering::SeqCst);
+        service.stats.processed.store(2, Ordering::SeqCst);
+        service.stats.failed.store(3, Ordering::SeqCst);
+        service.stats.rejected.store(4, Ordering::SeqCst);
+        assert_eq!(get(&mut service, "/healthz"), (StatusCode::OK, String::from("ok")));
+        assert_eq!(get(&mut service, "/readyz").0, StatusCode::SERVICE_UNAVAILABLE);
+        let (status, body) = get(&mut service, "/stats");
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&body).unwrap(),
+            json!({"queue_size": 1, "queue_depth": 1, "processed": 2, "failed": 3, "rejected": 4})
+        );
+        assert_eq!(get(&mut service, "/unknown").0, StatusCode::METHOD_NOT_ALLOWED);
+    }
+}
@@ -3,8 +3,10 @@
 #![recursion_limit = "128"]
 
 mod api;
+mod blocking;
 mod executor;
 mod handler;
+#[cfg(feature = "webhook")]
 mod never;
 mod request;
 
@@ -17,7 +19,13 @@ pub mod types;
 /// A "prelude" for users of the library
 pub mod prelude;
 
-pub use self::{api::*, handler::*};
+pub use self::{
+    api::*,
+    executor::{CassetteError, Certificate, Executor, RecordingExecutor, ReplayExecutor, SendLimits, TimeoutError},
+    handler::*,
+    request::{Form, FormValue, Request, RequestBody, RequestMethod},
+};
+#[cfg(feature = "webhook")]
 use never::Never;
 
 pub use mime;
@@ -7,12 +7,13 @@ mod message;
 mod method;
 mod passport;
 mod poll;
+mod raw;
 mod send;
 mod sticker;
 mod updates;
 mod user;
 
 pub use self::{
-    answer::*, chat::*, chat_member::*, game::*, get_file::*, message::*, method::*, passport::*, poll::*, send::*,
-    sticker::*, updates::*, user::*,
+    answer::*, chat::*, chat_member::*, game::*, get_file::*, message::*, method::*, passport::*, poll::*, raw::*,
+    send::*, sticker::*, updates::*, user::*,
 };
@@ -0,0 +1,178 @@
+use crate::{
+    methods::Method,
+    request::{Form, RequestBuilder},
+    types::InputFile,
+};
+use failure::Error;
+use serde::de::DeserializeOwned;
+use serde_json::Value as JsonValue;
+use std::{fmt, marker::PhantomData};
+
+/// Calls an API method by name
+///
+/// Use it when a method is not supported by the library yet.
+/// Result is returned as `serde_json::Value` by default,
+/// use [response()](#method.response) in order to deserialize it into another type.
+pub struct RawMethod<T = JsonValue> {
+    name: String,
+    params: JsonValue,
+    files: Vec<(String, InputFile)>,
+    response: PhantomData<fn() -> T>,
+}
+
+impl RawMethod {
+    /// Creates a new RawMethod
+    ///
+    /// # Arguments
+    ///
+    /// * name - Name of the method (e.g. `sendMessage`)
+    /// * params - Parameters of the method, an object or null
+    pub fn new<S: Into<String>>(name: S, params: JsonValue) -> Self {
+        RawMethod {
+            name: name.into(),
+            params,
+            files: Vec::new(),
+            response: PhantomData,
+        }
+    }
+}
+
+impl<T> RawMethod<T> {
+    /// Adds a file to upload
+    ///
+    /// Request is sent as multipart/form-data when at least one file is added,
+    /// parameters which are not strings are encoded as JSON
+    pub fn file<N, F>(mut self, name: N, file: F) -> Self
+    where
+        N: Into<String>,
+        F: Into<InputFile>,
+    {
+        self.files.push((name.into(), file.into()));
+        self
+    }
+
+    /// Sets a type of successful result
+    pub fn response<R: DeserializeOwned>(self) -> RawMethod<R> {
+        RawMethod {
+            name: self.name,
+            params: self.params,
+            files: self.files,
+            response: PhantomData,
+        }
+    }
+}
+
+impl<T> fmt::Debug for RawMethod<T> {
+    fn fmt(&self, out: &mut fmt::Formatter<'_>) -> fmt::Result {
+        out.debug_struct("RawMethod")
+            .field("name", &self.name)
+            .field("params", &self.params)
+            .field("files", &self.files)
+            .finish()
+    }
+}
+
+impl<T: DeserializeOwned> Method for RawMethod<T> {
+    type Response = T;
+
+    fn into_request(self) -> Result<RequestBuilder, Error> {
+        if self.files.is_empty() {
+            return match self.params {
+                JsonValue::Null => RequestBuilder::json(self.name, &JsonValue::Object(Default::default())),
+                JsonValue::Object(_) => RequestBuilder::json(self.name, &self.params),
+                _ => Err(RawMethodError::UnexpectedParams.into()),
+            };
+        }
+        let mut form = Form::new();
+        match self.params {
+            JsonValue::Null => {}
+            JsonValue::Object(params) => {
+                for (key, value) in params {
+                    match value {
+                        JsonValue::Null => {}
+                        JsonValue::String(value) => form.insert_field(key, value),
+                        value => form.insert_field(key, serde_json::to_string(&value)?),
+                    }
+                }
+            }
+            _ => return Err(RawMethodError::UnexpectedParams.into()),
+        }
+        for (name, file) in self.files {
+            form.insert_field(name, file);
+        }
+        RequestBuilder::form(self.name, form)
+    }
+}
+
+/// An error when building a raw method request
+#[derive(Debug, failure::Fail)]
+pub enum RawMethodError {
+    /// Parameters are neither an object nor null
+    #[fail(display = "Parameters of a raw method must be an object or null")]
+    UnexpectedParams,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        request::{RequestBody, RequestMethod},
+        types::User,
+    };
+    use serde_json::json;
+
+    #[test]
+    fn raw_method_json() {
+        let request = RawMethod::new("getMe", JsonValue::Null)
+            .into_request()
+            .unwrap()
+            .build("base-url", "token");
+        assert_eq!(request.method, RequestMethod::Post);
+        assert_eq!(request.url, "base-url/bottoken/getMe");
+        if let RequestBody::Json(data) = request.body {
+            assert_eq!(String::from_utf8(data).unwrap(), "{}");
+        } else {
+            panic!("Unexpected request body: {:?}", request.body);
+        }
+
+        let method = RawMethod::new("newMethod", json!({"chat_id": 1, "text": "test"})).response::<User>();
+        let request = method.into_request().unwrap().build("base-url", "token");
+        assert_eq!(request.url, "base-url/bottoken/newMethod");
+        if let RequestBody::Json(data) = request.body {
+            let data: JsonValue = serde_json::from_slice(&data).unwrap();
+            assert_eq!(data, json!({"chat_id": 1, "text": "test"}));
+        } else {
+            panic!("Unexpected request body: {:?}", request.body);
+        }
+
+        assert!(RawMethod::new("newMethod", json!([1])).into_request().is_err());
+    }
+
+    #[test]
+    fn raw_method_form() {
+        let request = RawMethod::new(
+            "newMethod",
+            json!({"chat_id": 1, "text": "test", "options": ["a", "b"], "skip": null}),
+        )
+        .file("document", InputFile::file_id("file-id"))
+        .into_request()
+        .unwrap()
+        .build("base-url", "token");
+        assert_eq!(request.method, RequestMethod::Post);
+        assert_eq!(request.url, "base-url/bottoken/newMethod");
+        if let RequestBody::Form(form) = request.body {
+            assert_eq!(form.get_field("chat_id").unwrap().get_text().unwrap(), "1");
+            assert_eq!(form.get_field("text").unwrap().get_text().unwrap(), "test");
+            assert_eq!(form.get_field("options").unwrap().get_text().unwrap(), r#"["a","b"]"#);
+            assert!(form.get_field("skip").is_none());
+            assert!(form.get_field("document").unwrap().get_file().is_some());
+        } else {
+            panic!("Unexpected request body: {:?}", request.body);
+        }
+
+        assert!(RawMethod::new("newMethod", json!("test"))
+            .file("document", InputFile::file_id("file-id"))
+            .into_request()
+            .is_err());
+    }
+}
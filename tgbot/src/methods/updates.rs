@@ -1,7 +1,7 @@
 use crate::{
     methods::Method,
-    request::RequestBuilder,
-    types::{AllowedUpdate, Integer, Update, WebhookInfo},
+    request::{Form, RequestBuilder},
+    types::{AllowedUpdate, InputFile, Integer, Update, WebhookInfo},
 };
 use failure::Error;
 use serde::Serialize;
@@ -26,7 +26,11 @@ impl Method for GetUpdates {
     type Response = Vec<Update>;
 
     fn into_request(self) -> Result<RequestBuilder, Error> {
-        RequestBuilder::json("getUpdates", &self)
+        let builder = RequestBuilder::json("getUpdates", &self)?;
+        Ok(match self.timeout {
+            Some(timeout) => builder.poll_timeout(Duration::from_secs(timeout as u64)),
+            None => builder,
+        })
     }
 }
 
@@ -98,15 +102,21 @@ impl GetUpdates {
 /// If you'd like to make sure that the Webhook request comes from Telegram,
 /// we recommend using a secret path in the URL, e.g. https://www.example.com/<token>
 /// Since nobody else knows your bot‘s token, you can be pretty sure it’s us
-#[derive(Clone, Debug, Serialize)]
+///
+/// Request is sent as multipart/form-data when a certificate is set
+#[derive(Debug, Serialize)]
 pub struct SetWebhook {
     url: String,
+    #[serde(skip)]
+    certificate: Option<InputFile>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    certificate: Option<String>,
+    ip_address: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     max_connections: Option<Integer>,
     #[serde(skip_serializing_if = "Option::is_none")]
     allowed_updates: Option<HashSet<AllowedUpdate>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    secret_token: Option<String>,
 }
 
 impl SetWebhook {
@@ -120,17 +130,28 @@ impl SetWebhook {
         SetWebhook {
             url: url.into(),
             certificate: None,
+            ip_address: None,
             max_connections: None,
             allowed_updates: None,
+            secret_token: None,
         }
     }
 
     /// Upload your public key certificate so that the root certificate in use can be checked
-    pub fn certificate<C: Into<String>>(mut self, certificate: C) -> Self {
+    ///
+    /// Required for self-signed certificates, use [InputFile::path](../types/struct.InputFile.html#method.path)
+    /// or [InputFile::reader](../types/struct.InputFile.html#method.reader)
+    pub fn certificate<C: Into<InputFile>>(mut self, certificate: C) -> Self {
         self.certificate = Some(certificate.into());
         self
     }
 
+    /// The fixed IP address which will be used to send webhook requests instead of the IP address resolved through DNS
+    pub fn ip_address<S: Into<String>>(mut self, ip_address: S) -> Self {
+        self.ip_address = Some(ip_address.into());
+        self
+    }
+
     /// Maximum allowed number of simultaneous HTTPS connections to the webhook for update delivery, 1-100
     ///
     /// Defaults to 40
@@ -168,13 +189,41 @@ impl SetWebhook {
         };
         self
     }
+
+    /// A secret token to be sent in a header “X-Telegram-Bot-Api-Secret-Token” in every webhook request
+    ///
+    /// 1-256 characters, only A-Z, a-z, 0-9, _ and - are allowed
+    /// The header is useful to ensure that the request comes from a webhook set by you
+    pub fn secret_token<S: Into<String>>(mut self, secret_token: S) -> Self {
+        self.secret_token = Some(secret_token.into());
+        self
+    }
 }
 
 impl Method for SetWebhook {
     type Response = bool;
 
-    fn into_request(self) -> Result<RequestBuilder, Error> {
-        RequestBuilder::json("setWebhook", &self)
+    fn into_request(mut self) -> Result<RequestBuilder, Error> {
+        let certificate = match self.certificate.take() {
+            Some(certificate) => certificate,
+            None => return RequestBuilder::json("setWebhook", &self),
+        };
+        let mut form = Form::new();
+        form.insert_field("url", self.url);
+        form.insert_field("certificate", certificate);
+        if let Some(ip_address) = self.ip_address {
+            form.insert_field("ip_address", ip_address);
+        }
+        if let Some(max_connections) = self.max_connections {
+            form.insert_field("max_connections", max_connections);
+        }
+        if let Some(allowed_updates) = self.allowed_updates {
+            form.insert_field("allowed_updates", serde_json::to_string(&allowed_updates)?);
+        }
+        if let Some(secret_token) = self.secret_token {
+            form.insert_field("secret_token", secret_token);
+        }
+        RequestBuilder::form("setWebhook", form)
     }
 }
 
@@ -212,7 +261,9 @@ mod tests {
 
     #[test]
     fn get_updates() {
-        let req = GetUpdates::default().into_request().unwrap().build("host", "token");
+        let builder = GetUpdates::default().into_request().unwrap();
+        assert!(builder.get_poll_timeout().is_none());
+        let req = builder.build("host", "token");
         assert_eq!(req.method, RequestMethod::Post);
         assert_eq!(req.url, "host/bottoken/getUpdates");
         match req.body {
@@ -229,7 +280,7 @@ mod tests {
         updates.insert(AllowedUpdate::ChannelPost);
         updates.insert(AllowedUpdate::EditedChannelPost);
         updates.insert(AllowedUpdate::ChosenInlineResult);
-        let req = GetUpdates::default()
+        let builder = GetUpdates::default()
             .offset(0)
             .limit(10)
             .timeout(Duration::from_secs(10))
@@ -239,8 +290,9 @@ mod tests {
             .add_allowed_update(AllowedUpdate::PreCheckoutQuery)
             .add_allowed_update(AllowedUpdate::ShippingQuery)
             .into_request()
-            .unwrap()
-            .build("host", "token");
+            .unwrap();
+        assert_eq!(builder.get_poll_timeout(), Some(Duration::from_secs(10)));
+        let req = builder.build("host", "token");
         match req.body {
             RequestBody::Json(data) => {
                 let data: Value = serde_json::from_slice(&data).unwrap();;
@@ -296,13 +348,14 @@ mod tests {
         updates.insert(AllowedUpdate::EditedChannelPost);
         updates.insert(AllowedUpdate::ChosenInlineResult);
         let req = SetWebhook::new("url")
-            .certificate("cert")
+            .ip_address("127.0.0.1")
             .max_connections(10)
             .allowed_updates(updates)
             .add_allowed_update(AllowedUpdate::InlineQuery)
             .add_allowed_update(AllowedUpdate::CallbackQuery)
             .add_allowed_update(AllowedUpdate::PreCheckoutQuery)
             .add_allowed_update(AllowedUpdate::ShippingQuery)
+            .secret_token("secret")
             .into_request()
             .unwrap()
             .build("host", "token");
@@ -311,8 +364,10 @@ mod tests {
         match req.body {
             RequestBody::Json(data) => {
                 let data: Value = serde_json::from_slice(&data).unwrap();
-                assert_eq!(data["certificate"], "cert");
+                assert!(data.get("certificate").is_none());
+                assert_eq!(data["ip_address"], "127.0.0.1");
                 assert_eq!(data["max_connections"], 10);
+                assert_eq!(data["secret_token"], "secret");
                 let mut updates: Vec<&str> = data["allowed_updates"]
                     .as_array()
                     .unwrap()
@@ -340,6 +395,29 @@ mod tests {
 
         let method = SetWebhook::new("url").add_allowed_update(AllowedUpdate::Message);
         assert_eq!(method.allowed_updates.unwrap().len(), 1);
+
+        let req = SetWebhook::new("url")
+            .certificate(InputFile::path("cert.pem"))
+            .ip_address("127.0.0.1")
+            .max_connections(10)
+            .add_allowed_update(AllowedUpdate::Message)
+            .secret_token("secret")
+            .into_request()
+            .unwrap()
+            .build("host", "token");
+        assert_eq!(req.method, RequestMethod::Post);
+        assert_eq!(req.url, "host/bottoken/setWebhook");
+        match req.body {
+            RequestBody::Form(form) => {
+                assert_eq!(form.fields["url"].get_text().unwrap(), "url");
+                assert!(form.fields["certificate"].get_file().is_some());
+                assert_eq!(form.fields["ip_address"].get_text().unwrap(), "127.0.0.1");
+                assert_eq!(form.fields["max_connections"].get_text().unwrap(), "10");
+                assert_eq!(form.fields["allowed_updates"].get_text().unwrap(), r#"["message"]"#);
+                assert_eq!(form.fields["secret_token"].get_text().unwrap(), "secret");
+            }
+            data => panic!("Unexpected request data: {:?}", data),
+        }
     }
 
     #[test]
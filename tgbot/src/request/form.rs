@@ -1,24 +1,28 @@
-use crate::types::{InputFile, InputFileInfo, InputFileKind, InputFileReader};
-use hyper_multipart_rfc7578::client::multipart::Form as MultipartForm;
-use std::collections::HashMap;
+use crate::types::InputFile;
+use std::collections::{hash_map::IntoIter as HashMapIntoIter, HashMap};
+#[cfg(any(test, not(feature = "multipart")))]
+use url::form_urlencoded::Serializer;
 
+/// A value of a form field
 #[derive(Debug)]
-pub(crate) enum FormValue {
+pub enum FormValue {
+    /// Text value
     Text(String),
+    /// File to upload
     File(InputFile),
 }
 
 impl FormValue {
-    #[cfg(test)]
-    pub(crate) fn get_text(&self) -> Option<&str> {
+    /// Returns a text if value is not a file
+    pub fn get_text(&self) -> Option<&str> {
         match self {
             FormValue::Text(ref text) => Some(text),
             FormValue::File(_) => None,
         }
     }
 
-    #[cfg(test)]
-    pub(crate) fn get_file(&self) -> Option<&InputFile> {
+    /// Returns a file if value is not a text
+    pub fn get_file(&self) -> Option<&InputFile> {
         match self {
             FormValue::Text(_) => None,
             FormValue::File(ref file) => Some(file),
@@ -41,53 +45,74 @@ impl From<InputFile> for FormValue {
     }
 }
 
+/// Fields of a multipart/form-data request
 #[derive(Debug)]
-pub(crate) struct Form {
+pub struct Form {
     pub(crate) fields: HashMap<String, FormValue>,
 }
 
 impl Form {
-    pub(crate) fn new() -> Self {
+    /// Creates an empty form
+    pub fn new() -> Self {
         Self { fields: HashMap::new() }
     }
 
-    pub(crate) fn insert_field<N, V>(&mut self, name: N, value: V)
+    /// Inserts a field, an existing value with the same name will be replaced
+    pub fn insert_field<N, V>(&mut self, name: N, value: V)
     where
         N: Into<String>,
         V: Into<FormValue>,
     {
         self.fields.insert(name.into(), value.into());
     }
-}
 
-impl From<Form> for MultipartForm<'static> {
-    fn from(form: Form) -> Self {
-        let mut result = MultipartForm::default();
-        for (field_name, field_value) in form.fields {
-            match field_value {
-                FormValue::Text(text) => result.add_text(field_name, text),
-                FormValue::File(file) => match file.kind {
-                    InputFileKind::Path(path) => result.add_file(field_name, path).unwrap(),
-                    InputFileKind::Reader(InputFileReader {
-                        reader,
-                        info: file_info,
-                    }) => match file_info {
-                        Some(InputFileInfo {
-                            name: file_name,
-                            mime_type: Some(mime_type),
-                        }) => result.add_reader_file_with_mime(field_name, reader, file_name, mime_type),
-                        Some(InputFileInfo {
-                            name: file_name,
-                            mime_type: None,
-                        }) => result.add_reader_file(field_name, reader, file_name),
-                        None => result.add_reader(field_name, reader),
-                    },
-                    InputFileKind::Id(file_id) => result.add_text(field_name, file_id),
-                    InputFileKind::Url(url) => result.add_text(field_name, url),
-                },
-            }
+    /// Returns a value of a field
+    pub fn get_field(&self, name: &str) -> Option<&FormValue> {
+        self.fields.get(name)
+    }
+
+    /// Returns a copy of the form if it does not contain readers or streams
+    pub(crate) fn try_clone(&self) -> Option<Form> {
+        let mut fields = HashMap::with_capacity(self.fields.len());
+        for (name, value) in &self.fields {
+            let value = match value {
+                FormValue::Text(text) => FormValue::Text(text.clone()),
+                FormValue::File(file) => FormValue::File(file.try_clone()?),
+            };
+            fields.insert(name.clone(), value);
         }
-        result
+        Some(Form { fields })
+    }
+
+    /// Returns an application/x-www-form-urlencoded body
+    ///
+    /// Returns None if the form contains a file to upload
+    #[cfg(any(test, not(feature = "multipart")))]
+    pub(crate) fn into_urlencoded(self) -> Option<String> {
+        let mut serializer = Serializer::new(String::new());
+        for (name, value) in self.fields {
+            let value = match value {
+                FormValue::Text(text) => text,
+                FormValue::File(file) => file.into_kind().get_text()?,
+            };
+            serializer.append_pair(&name, &value);
+        }
+        Some(serializer.finish())
+    }
+}
+
+impl Default for Form {
+    fn default() -> Self {
+        Form::new()
+    }
+}
+
+impl IntoIterator for Form {
+    type Item = (String, FormValue);
+    type IntoIter = HashMapIntoIter<String, FormValue>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.fields.into_iter()
     }
 }
 
@@ -115,6 +140,43 @@ mod tests {
         form.insert_field("id", InputFile::url("url"));
         form.insert_field("id", InputFile::path("file-path"));
         form.insert_field("id", InputFile::from(Cursor::new(b"test")));
-        MultipartForm::from(form);
+        assert!(form.get_field("id").unwrap().get_file().is_some());
+        assert!(form.get_field("unknown").is_none());
+        assert_eq!(form.into_iter().count(), 1);
+    }
+
+    #[test]
+    fn form_try_clone() {
+        let mut form = Form::new();
+        form.insert_field("text", 1);
+        form.insert_field("file", InputFile::file_id("file-id"));
+        let form = form.try_clone().unwrap();
+        assert_eq!(form.fields["text"].get_text().unwrap(), "1");
+        assert!(form.fields["file"].get_file().is_some());
+
+        let mut form = Form::new();
+        form.insert_field("text", 1);
+        form.insert_field("file", InputFile::from(Cursor::new(b"test")));
+        assert!(form.try_clone().is_none());
+    }
+
+    #[test]
+    fn form_into_urlencoded() {
+        let mut form = Form::new();
+        form.insert_field("chat_id", 1);
+        form.insert_field("photo", InputFile::url("https://example.com/a b.png"));
+        let body = form.into_urlencoded().unwrap();
+        let mut pairs: Vec<_> = body.split('&').collect();
+        pairs.sort();
+        assert_eq!(pairs, ["chat_id=1", "photo=https%3A%2F%2Fexample.com%2Fa+b.png"]);
+
+        let mut form = Form::new();
+        form.insert_field("chat_id", 1);
+        form.insert_field("photo", InputFile::path("file-path"));
+        assert!(form.into_urlencoded().is_none());
+
+        let mut form = Form::new();
+        form.insert_field("photo", InputFile::from(Cursor::new(b"test")));
+        assert!(form.into_urlencoded().is_none());
     }
 }
@@ -1,9 +1,16 @@
+#[cfg(feature = "webhook")]
+use crate::handler::WebhookReplyError;
+use crate::types::{ChatId, Integer};
 use failure::Error;
 use serde::ser::Serialize;
+use serde_json::Value as JsonValue;
+use std::time::Duration;
 
 mod form;
+#[cfg(feature = "multipart")]
+mod multipart;
 
-pub(crate) use self::form::*;
+pub use self::form::*;
 
 /// A request builder
 #[derive(Debug)]
@@ -11,6 +18,7 @@ pub struct RequestBuilder {
     method: RequestMethod,
     path: String,
     body: RequestBody,
+    poll_timeout: Option<Duration>,
 }
 
 impl RequestBuilder {
@@ -19,6 +27,7 @@ impl RequestBuilder {
             method: RequestMethod::Post,
             body: RequestBody::Form(form),
             path: path.into(),
+            poll_timeout: None,
         })
     }
 
@@ -27,6 +36,7 @@ impl RequestBuilder {
             method: RequestMethod::Post,
             body: RequestBody::Json(serde_json::to_vec(s)?),
             path: path.into(),
+            poll_timeout: None,
         })
     }
 
@@ -35,34 +45,293 @@ impl RequestBuilder {
             method: RequestMethod::Get,
             body: RequestBody::Empty,
             path: path.into(),
+            poll_timeout: None,
         })
     }
 
+    /// Marks a request as long polling request with a given timeout
+    pub(crate) fn poll_timeout(mut self, poll_timeout: Duration) -> Self {
+        self.poll_timeout = Some(poll_timeout);
+        self
+    }
+
+    pub(crate) fn get_path(&self) -> &str {
+        &self.path
+    }
+
+    pub(crate) fn get_poll_timeout(&self) -> Option<Duration> {
+        self.poll_timeout
+    }
+
+    /// Converts a request into a body of webhook response
+    ///
+    /// Fails when request contains a file to upload or JSON body is not an object
+    #[cfg(feature = "webhook")]
+    pub(crate) fn into_webhook_reply(self) -> Result<Vec<u8>, WebhookReplyError> {
+        let mut params = match self.body {
+            RequestBody::Json(data) => match serde_json::from_slice(&data) {
+                Ok(JsonValue::Object(params)) => params,
+                _ => return Err(WebhookReplyError::InvalidBody),
+            },
+            RequestBody::Form(form) => {
+                let mut params = serde_json::Map::new();
+                for (name, value) in form {
+                    let value = match value {
+                        FormValue::Text(text) => text,
+                        FormValue::File(file) => file.into_kind().get_text().ok_or(WebhookReplyError::Upload)?,
+                    };
+                    params.insert(name, JsonValue::String(value));
+                }
+                params
+            }
+            RequestBody::Empty => serde_json::Map::new(),
+        };
+        params.insert(String::from("method"), JsonValue::String(self.path));
+        serde_json::to_vec(&params).map_err(|_| WebhookReplyError::InvalidBody)
+    }
+
     pub(crate) fn build(self, base_url: &str, token: &str) -> Request {
         Request {
             method: self.method,
             url: format!("{}/bot{}/{}", base_url, token, self.path),
             body: self.body,
+            timeout: None,
         }
     }
 }
 
+/// An HTTP request to be sent by [Executor](trait.Executor.html)
 #[derive(Debug)]
-pub(crate) struct Request {
-    pub(crate) method: RequestMethod,
-    pub(crate) url: String,
-    pub(crate) body: RequestBody,
+pub struct Request {
+    /// HTTP method
+    pub method: RequestMethod,
+    /// Full URL including a bot token
+    pub url: String,
+    /// Request body
+    pub body: RequestBody,
+    /// Request will be cancelled when timeout is reached
+    ///
+    /// Timeout is enforced by Api, so executors are not required to handle it.
+    pub timeout: Option<Duration>,
 }
 
+impl Request {
+    /// Returns a copy of the request if its body can be copied
+    pub(crate) fn try_clone(&self) -> Option<Request> {
+        Some(Request {
+            method: self.method,
+            url: self.url.clone(),
+            body: match self.body {
+                RequestBody::Form(ref form) => RequestBody::Form(form.try_clone()?),
+                RequestBody::Json(ref data) => RequestBody::Json(data.clone()),
+                RequestBody::Empty => RequestBody::Empty,
+            },
+            timeout: self.timeout,
+        })
+    }
+
+    /// Returns chat_id parameter of the request
+    pub(crate) fn get_chat_id(&self) -> Option<ChatId> {
+        match self.body {
+            RequestBody::Form(ref form) => form.fields.get("chat_id").and_then(|value| match value {
+                FormValue::Text(text) => Some(match text.parse::<Integer>() {
+                    Ok(chat_id) => ChatId::Id(chat_id),
+                    Err(_) => ChatId::Username(text.clone()),
+                }),
+                FormValue::File(_) => None,
+            }),
+            RequestBody::Json(ref data) => {
+                let value: JsonValue = serde_json::from_slice(data).ok()?;
+                match value.get("chat_id")? {
+                    JsonValue::Number(chat_id) => chat_id.as_i64().map(ChatId::Id),
+                    JsonValue::String(username) => Some(ChatId::Username(username.clone())),
+                    _ => None,
+                }
+            }
+            RequestBody::Empty => None,
+        }
+    }
+
+    /// Replaces chat_id parameter of the request
+    ///
+    /// Returns `false` when the request has no chat_id parameter
+    pub(crate) fn replace_chat_id(&mut self, chat_id: Integer) -> Result<bool, Error> {
+        match self.body {
+            RequestBody::Form(ref mut form) => {
+                if form.fields.contains_key("chat_id") {
+                    form.insert_field("chat_id", chat_id);
+                    Ok(true)
+                } else {
+                    Ok(false)
+                }
+            }
+            RequestBody::Json(ref mut data) => {
+                let mut value: JsonValue = serde_json::from_slice(data)?;
+                match value.get_mut("chat_id") {
+                    Some(old_chat_id) => *old_chat_id = chat_id.into(),
+                    None => return Ok(false),
+                }
+                *data = serde_json::to_vec(&value)?;
+                Ok(true)
+            }
+            RequestBody::Empty => Ok(false),
+        }
+    }
+}
+
+/// HTTP method of a request
 #[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
-pub(crate) enum RequestMethod {
+pub enum RequestMethod {
+    /// GET
     Get,
+    /// POST
     Post,
 }
 
+/// Body of a request
 #[derive(Debug)]
-pub(crate) enum RequestBody {
+pub enum RequestBody {
+    /// Fields to be sent using multipart/form-data
     Form(Form),
+    /// Serialized JSON to be sent with application/json content type
     Json(Vec<u8>),
+    /// Request has no body
     Empty,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::InputFile;
+    use serde_json::json;
+    use std::io::Cursor;
+
+    #[test]
+    fn try_clone() {
+        let req = RequestBuilder::json("method", &json!({"chat_id": 1}))
+            .unwrap()
+            .build("host", "token");
+        let copy = req.try_clone().unwrap();
+        assert_eq!(copy.method, RequestMethod::Post);
+        assert_eq!(copy.url, "host/bottoken/method");
+        match copy.body {
+            RequestBody::Json(data) => assert_eq!(data, br#"{"chat_id":1}"#),
+            body => panic!("Unexpected request body: {:?}", body),
+        }
+
+        let req = RequestBuilder::empty("method").unwrap().build("host", "token");
+        assert!(req.try_clone().is_some());
+
+        let mut form = Form::new();
+        form.insert_field("file", InputFile::from(Cursor::new(b"test")));
+        let req = RequestBuilder::form("method", form).unwrap().build("host", "token");
+        assert!(req.try_clone().is_none());
+    }
+
+    #[test]
+    fn get_chat_id() {
+        let req = RequestBuilder::json("method", &json!({"chat_id": 1}))
+            .unwrap()
+            .build("host", "token");
+        assert_eq!(req.get_chat_id(), Some(ChatId::Id(1)));
+        let req = RequestBuilder::json("method", &json!({"chat_id": "@username"}))
+            .unwrap()
+            .build("host", "token");
+        assert_eq!(req.get_chat_id(), Some(ChatId::from("@username")));
+        let req = RequestBuilder::json("method", &json!({}))
+            .unwrap()
+            .build("host", "token");
+        assert!(req.get_chat_id().is_none());
+
+        let mut form = Form::new();
+        form.insert_field("chat_id", -1);
+        let req = RequestBuilder::form("method", form).unwrap().build("host", "token");
+        assert_eq!(req.get_chat_id(), Some(ChatId::Id(-1)));
+        let mut form = Form::new();
+        form.insert_field("chat_id", "@username");
+        let req = RequestBuilder::form("method", form).unwrap().build("host", "token");
+        assert_eq!(req.get_chat_id(), Some(ChatId::from("@username")));
+
+        let req = RequestBuilder::empty("method").unwrap().build("host", "token");
+        assert!(req.get_chat_id().is_none());
+    }
+
+    #[test]
+    fn replace_chat_id() {
+        let mut req = RequestBuilder::json("method", &json!({"chat_id": 1, "text": "test"}))
+            .unwrap()
+            .build("host", "token");
+        assert!(req.replace_chat_id(2).unwrap());
+        match req.body {
+            RequestBody::Json(data) => {
+                let data: JsonValue = serde_json::from_slice(&data).unwrap();
+                assert_eq!(data, json!({"chat_id": 2, "text": "test"}));
+            }
+            body => panic!("Unexpected request body: {:?}", body),
+        }
+
+        let mut req = RequestBuilder::json("method", &json!({"text": "test"}))
+            .unwrap()
+            .build("host", "token");
+        assert!(!req.replace_chat_id(2).unwrap());
+
+        let mut form = Form::new();
+        form.insert_field("chat_id", 1);
+        let mut req = RequestBuilder::form("method", form).unwrap().build("host", "token");
+        assert!(req.replace_chat_id(2).unwrap());
+        match req.body {
+            RequestBody::Form(form) => assert_eq!(form.fields["chat_id"].get_text().unwrap(), "2"),
+            body => panic!("Unexpected request body: {:?}", body),
+        }
+
+        let mut req = RequestBuilder::form("method", Form::new())
+            .unwrap()
+            .build("host", "token");
+        assert!(!req.replace_chat_id(2).unwrap());
+
+        let mut req = RequestBuilder::empty("method").unwrap().build("host", "token");
+        assert!(!req.replace_chat_id(2).unwrap());
+    }
+
+    #[cfg(feature = "webhook")]
+    #[test]
+    fn into_webhook_reply() {
+        let reply = RequestBuilder::json("sendMessage", &json!({"chat_id": 1, "text": "test"}))
+            .unwrap()
+            .into_webhook_reply()
+            .unwrap();
+        let reply: JsonValue = serde_json::from_slice(&reply).unwrap();
+        assert_eq!(reply, json!({"method": "sendMessage", "chat_id": 1, "text": "test"}));
+
+        let reply = RequestBuilder::empty("getMe").unwrap().into_webhook_reply().unwrap();
+        assert_eq!(reply, br#"{"method":"getMe"}"#);
+
+        let mut form = Form::new();
+        form.insert_field("chat_id", 1);
+        form.insert_field("document", InputFile::file_id("file-id"));
+        let reply = RequestBuilder::form("sendDocument", form)
+            .unwrap()
+            .into_webhook_reply()
+            .unwrap();
+        let reply: JsonValue = serde_json::from_slice(&reply).unwrap();
+        assert_eq!(
+            reply,
+            json!({"method": "sendDocument", "chat_id": "1", "document": "file-id"})
+        );
+
+        let mut form = Form::new();
+        form.insert_field("document", InputFile::from(Cursor::new(b"test")));
+        match RequestBuilder::form("sendDocument", form).unwrap().into_webhook_reply() {
+            Err(WebhookReplyError::Upload) => {}
+            result => panic!("Unexpected result: {:?}", result),
+        }
+
+        match RequestBuilder::json("method", &json!([1, 2]))
+            .unwrap()
+            .into_webhook_reply()
+        {
+            Err(WebhookReplyError::InvalidBody) => {}
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use crate::{
+    request::{Form, FormValue},
+    types::{to_file_uri, InputFileInfo, InputFileKind, InputFileReader},
+};
+use common_multipart_rfc7578::client::multipart::{Body as MultipartBody, Form as MultipartForm};
+use failure::{format_err, Error};
+use futures::{stream, try_ready, Async, Stream};
+
+type BodyStream = Box<dyn Stream<Item = Vec<u8>, Error = Error> + Send>;
+
+impl Form {
+    /// Converts the form into a content type and a multipart/form-data body
+    ///
+    /// Streams are written before other fields, so they are sent without buffering
+    pub(crate) fn into_multipart(self) -> Result<(String, BodyStream), Error> {
+        let mut form = MultipartForm::default();
+        let mut has_parts = false;
+        let mut streams = Vec::new();
+        for (field_name, field_value) in self.fields {
+            match field_value {
+                FormValue::Text(text) => form.add_text(field_name, text),
+                FormValue::File(file) => match file.kind {
+                    InputFileKind::Path(path) => form.add_file(field_name, path)?,
+                    InputFileKind::Reader(InputFileReader {
+                        reader,
+                        info: file_info,
+                    }) => match file_info {
+                        Some(InputFileInfo {
+                            name: file_name,
+                            mime_type: Some(mime_type),
+                        }) => form.add_reader_file_with_mime(field_name, reader, file_name, mime_type),
+                        Some(InputFileInfo {
+                            name: file_name,
+                            mime_type: None,
+                        }) => form.add_reader_file(field_name, reader, file_name),
+                        None => form.add_reader(field_name, reader),
+                    },
+                    InputFileKind::Stream(file_stream) => {
+                        streams.push((field_name, file_stream));
+                        continue;
+                    }
+                    InputFileKind::Id(file_id) => form.add_text(field_name, file_id),
+                    InputFileKind::Url(url) => form.add_text(field_name, url),
+                    InputFileKind::LocalPath(path) => form.add_text(field_name, to_file_uri(&path)),
+                },
+            }
+            has_parts = true;
+        }
+        let content_type = form.content_type();
+        let boundary = content_type
+            .split("boundary=")
+            .nth(1)
+            .map(|boundary| boundary.trim_matches('"').to_string())
+            .ok_or_else(|| format_err!("Can not get a boundary from {}", content_type))?;
+        let mut body: BodyStream = Box::new(stream::empty());
+        for (field_name, file_stream) in streams {
+            let mut disposition = format!("form-data; name=\"{}\"", field_name);
+            let mut mime_type = mime::APPLICATION_OCTET_STREAM;
+            if let Some(info) = file_stream.info {
+                disposition.push_str(&format!("; filename=\"{}\"", info.name));
+                if let Some(info_mime_type) = info.mime_type {
+                    mime_type = info_mime_type;
+                }
+            }
+            let headers = format!(
+                "\r\n--{}\r\ncontent-type: {}\r\ncontent-disposition: {}\r\n\r\n",
+                boundary, mime_type, disposition
+            );
+            body = Box::new(
+                body.chain(stream::once(Ok(headers.into_bytes())))
+                    .chain(check_length(file_stream.stream, file_stream.length)),
+            );
+        }
+        if has_parts {
+            body = Box::new(body.chain(MultipartBody::from(form).map(|chunk| chunk.to_vec()).from_err()));
+        } else {
+            body = Box::new(body.chain(stream::once(Ok(format!("\r\n--{}--", boundary).into_bytes()))));
+        }
+        Ok((content_type, body))
+    }
+}
+
+#[derive(Debug, failure::Fail)]
+#[fail(display = "Expected {} bytes in a stream, received {} bytes", expected, received)]
+struct StreamLengthError {
+    expected: u64,
+    received: u64,
+}
+
+fn check_length(mut inner: BodyStream, length: Option<u64>) -> BodyStream {
+    let expected = match length {
+        Some(length) => length,
+        None => return inner,
+    };
+    let mut received = 0;
+    Box::new(stream::poll_fn(move || {
+        let chunk = try_ready!(inner.poll());
+        match chunk {
+            Some(ref data) => received += data.len() as u64,
+            None if received == expected => {}
+            None => return Err(StreamLengthError { expected, received }.into()),
+        }
+        if received > expected {
+            return Err(StreamLengthError { expected, received }.into());
+        }
+        Ok(Async::Ready(chunk))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{InputFile, InputFileStream};
+    use futures::Future;
+    use std::io::Cursor;
+
+    #[test]
+    fn form_multipart() {
+        let mut form = Form::default();
+        form.insert_field("id", 1);
+        form.insert_field("id", InputFile::file_id("file-id"));
+        form.insert_field("id", InputFile::url("url"));
+        form.insert_field("id", InputFile::path("file-path"));
+        form.insert_field("id", InputFile::from(Cursor::new(b"test")));
+        assert!(form.into_multipart().is_ok());
+    }
+
+    fn create_stream(length: Option<u64>) -> InputFile {
+        let chunks = vec![b"chunk1".to_vec(), b"chunk2".to_vec()];
+        let mut stream = InputFileStream::new(stream::iter_ok::<_, Error>(chunks)).info(("name", mime::TEXT_PLAIN));
+        if let Some(length) = length {
+            stream = stream.length(length);
+        }
+        InputFile::from(stream)
+    }
+
+    #[test]
+    fn form_multipart_stream() {
+        let mut form = Form::new();
+        form.insert_field("text", "value");
+        form.insert_field("file", create_stream(Some(12)));
+        let (content_type, body) = form.into_multipart().unwrap();
+        let boundary = content_type.split("boundary=").nth(1).unwrap().trim_matches('"');
+        let body = String::from_utf8(body.concat2().wait().unwrap()).unwrap();
+        assert_eq!(
+            body,
+            format!(
+                concat!(
+                    "\r\n--{boundary}\r\ncontent-type: text/plain\r\n",
+                    "content-disposition: form-data; name=\"file\"; filename=\"name\"\r\n\r\nchunk1chunk2",
+                    "\r\n--{boundary}\r\ncontent-type: text/plain\r\n",
+                    "content-disposition: form-data; name=\"text\"\r\n\r\nvalue",
+                    "\r\n--{boundary}--"
+                ),
+                boundary = boundary
+            )
+        );
+
+        let mut form = Form::new();
+        form.insert_field("file", create_stream(None));
+        let (content_type, body) = form.into_multipart().unwrap();
+        let boundary = content_type.split("boundary=").nth(1).unwrap().trim_matches('"');
+        let body = String::from_utf8(body.concat2().wait().unwrap()).unwrap();
+        assert!(body.ends_with(&format!("chunk1chunk2\r\n--{}--", boundary)));
+
+        for length in &[11, 13] {
+            let mut form = Form::new();
+            form.insert_field("file", create_stream(Some(*length)));
+            let (_, body) = form.into_multipart().unwrap();
+            let err = body.concat2().wait().unwrap_err();
+            assert!(err.downcast::<StreamLengthError>().is_ok());
+        }
+    }
+}
@@ -170,7 +170,7 @@ pub struct SupergroupChat {
 }
 
 /// Chat ID or username
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub enum ChatId {
     /// @username of a chat
     Username(String),
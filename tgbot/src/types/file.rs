@@ -1,7 +1,14 @@
 use crate::types::primitive::Integer;
+use failure::Error;
+use futures::Stream;
 use mime::Mime;
 use serde::Deserialize;
-use std::{fmt, io::Read, path::PathBuf};
+use std::{
+    env, fmt,
+    io::Read,
+    path::{Path, PathBuf},
+};
+use url::Url;
 
 /// File ready to be downloaded
 ///
@@ -9,6 +16,9 @@ use std::{fmt, io::Read, path::PathBuf};
 /// It is guaranteed that the link will be valid for at least 1 hour
 /// When the link expires, a new one can be requested by calling getFile
 /// Maximum file size to download is 20 MB
+///
+/// When a local Bot API server is used, file_path is an absolute path to a file on the server
+/// (see [Config::local_mode()](../struct.Config.html#method.local_mode))
 #[derive(Clone, Debug, Deserialize)]
 pub struct File {
     /// Unique identifier for this file
@@ -41,6 +51,16 @@ impl InputFileInfo {
         self.mime_type = Some(mime_type);
         self
     }
+
+    /// Returns a file name
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns a mime type of a file
+    pub fn get_mime_type(&self) -> Option<&Mime> {
+        self.mime_type.as_ref()
+    }
 }
 
 impl From<&str> for InputFileInfo {
@@ -96,6 +116,16 @@ impl InputFileReader {
         self.info = Some(info.into());
         self
     }
+
+    /// Returns a file info
+    pub fn get_info(&self) -> Option<&InputFileInfo> {
+        self.info.as_ref()
+    }
+
+    /// Returns an underlying reader and a file info
+    pub fn into_inner(self) -> (Box<dyn Read + Send>, Option<InputFileInfo>) {
+        (self.reader, self.info)
+    }
 }
 
 impl<R> From<R> for InputFileReader
@@ -107,6 +137,66 @@ where
     }
 }
 
+/// Async stream of file chunks to upload
+///
+/// Chunks are sent as soon as they are received, a file is not buffered in memory
+pub struct InputFileStream {
+    // streams are uploaded as multipart/form-data only
+    #[cfg_attr(not(feature = "multipart"), allow(dead_code))]
+    pub(crate) stream: Box<dyn Stream<Item = Vec<u8>, Error = Error> + Send>,
+    pub(crate) info: Option<InputFileInfo>,
+    pub(crate) length: Option<u64>,
+}
+
+impl fmt::Debug for InputFileStream {
+    fn fmt(&self, out: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            out,
+            "InputFileStream(stream: ..., info: {:?}, length: {:?})",
+            self.info, self.length
+        )
+    }
+}
+
+impl InputFileStream {
+    /// Creates a new file stream
+    pub fn new<S, E>(stream: S) -> Self
+    where
+        S: Stream<Item = Vec<u8>, Error = E> + Send + 'static,
+        E: Into<Error> + 'static,
+    {
+        InputFileStream {
+            stream: Box::new(stream.map_err(Into::into)),
+            info: None,
+            length: None,
+        }
+    }
+
+    /// Sets a file info
+    pub fn info<I: Into<InputFileInfo>>(mut self, info: I) -> Self {
+        self.info = Some(info.into());
+        self
+    }
+
+    /// Sets a known length of a file
+    ///
+    /// Upload fails when stream yields a different number of bytes
+    pub fn length(mut self, length: u64) -> Self {
+        self.length = Some(length);
+        self
+    }
+
+    /// Returns a file info
+    pub fn get_info(&self) -> Option<&InputFileInfo> {
+        self.info.as_ref()
+    }
+
+    /// Returns a length of a file
+    pub fn get_length(&self) -> Option<u64> {
+        self.length
+    }
+}
+
 /// File to upload
 #[derive(Debug)]
 pub struct InputFile {
@@ -137,19 +227,96 @@ impl InputFile {
         }
     }
 
+    /// Path to file on a local Bot API server host
+    ///
+    /// File is not uploaded, it is sent as a `file://` URI,
+    /// so the path must be available for the server.
+    /// A relative path is resolved against current directory of the bot.
+    pub fn local_path<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            kind: InputFileKind::LocalPath(path.into()),
+        }
+    }
+
     /// A reader (file will be uploaded using multipart/form-data)
     pub fn reader<R: Into<InputFileReader>>(reader: R) -> Self {
         Self {
             kind: InputFileKind::Reader(reader.into()),
         }
     }
+
+    /// An async stream (file will be uploaded using multipart/form-data)
+    pub fn stream<S: Into<InputFileStream>>(stream: S) -> Self {
+        Self {
+            kind: InputFileKind::Stream(stream.into()),
+        }
+    }
+
+    /// Returns a kind of the file
+    pub fn get_kind(&self) -> &InputFileKind {
+        &self.kind
+    }
+
+    /// Converts the file into its kind
+    pub fn into_kind(self) -> InputFileKind {
+        self.kind
+    }
+
+    /// Returns a copy of the file, readers and streams can not be copied
+    pub(crate) fn try_clone(&self) -> Option<InputFile> {
+        let kind = match self.kind {
+            InputFileKind::Id(ref file_id) => InputFileKind::Id(file_id.clone()),
+            InputFileKind::Url(ref url) => InputFileKind::Url(url.clone()),
+            InputFileKind::Path(ref path) => InputFileKind::Path(path.clone()),
+            InputFileKind::LocalPath(ref path) => InputFileKind::LocalPath(path.clone()),
+            InputFileKind::Reader(_) | InputFileKind::Stream(_) => return None,
+        };
+        Some(InputFile { kind })
+    }
 }
 
-pub(crate) enum InputFileKind {
+/// Kind of a file to upload
+pub enum InputFileKind {
+    /// A file_id that exists on the Telegram servers
     Id(String),
+    /// An HTTP URL to get a file from the Internet
     Url(String),
+    /// Path to file in FS
     Path(PathBuf),
+    /// Path to file on a local Bot API server host
+    LocalPath(PathBuf),
+    /// A reader
     Reader(InputFileReader),
+    /// An async stream
+    Stream(InputFileStream),
+}
+
+impl InputFileKind {
+    /// Returns a text value for a kind sent without upload (file_id, URL or `file://` URI)
+    pub(crate) fn get_text(&self) -> Option<String> {
+        match self {
+            InputFileKind::Id(text) | InputFileKind::Url(text) => Some(text.clone()),
+            InputFileKind::LocalPath(path) => Some(to_file_uri(path)),
+            _ => None,
+        }
+    }
+}
+
+/// Converts a path into a percent-encoded `file://` URI
+///
+/// Relative path is resolved against current directory
+pub(crate) fn to_file_uri(path: &Path) -> String {
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir()
+            .map(|dir| dir.join(path))
+            .unwrap_or_else(|_| path.to_path_buf())
+    };
+    match Url::from_file_path(&path) {
+        Ok(url) => url.into_string(),
+        Err(()) => format!("file://{}", path.display()),
+    }
 }
 
 impl fmt::Debug for InputFileKind {
@@ -158,7 +325,9 @@ impl fmt::Debug for InputFileKind {
             InputFileKind::Id(ref s) => write!(out, "InputFileKind::Id({:?})", s),
             InputFileKind::Url(ref s) => write!(out, "InputFileKind::Url({:?})", s),
             InputFileKind::Path(ref s) => write!(out, "InputFileKind::Path({:?})", s),
+            InputFileKind::LocalPath(ref s) => write!(out, "InputFileKind::LocalPath({:?})", s),
             InputFileKind::Reader(ref r) => write!(out, "InputFileKind::Reader({:?})", r),
+            InputFileKind::Stream(ref s) => write!(out, "InputFileKind::Stream({:?})", s),
         }
     }
 }
@@ -169,6 +338,12 @@ impl From<InputFileReader> for InputFile {
     }
 }
 
+impl From<InputFileStream> for InputFile {
+    fn from(stream: InputFileStream) -> Self {
+        Self::stream(stream)
+    }
+}
+
 impl<R> From<R> for InputFile
 where
     R: Read + Send + 'static,
@@ -211,6 +386,7 @@ mod tests {
     fn input_file() {
         let id = InputFile::file_id("file-id");
         assert_eq!(format!("{:?}", id.kind), r#"InputFileKind::Id("file-id")"#);
+        assert_eq!(format!("{:?}", id.get_kind()), r#"InputFileKind::Id("file-id")"#);
         let url = InputFile::url("http://example.com/archive.zip");
         assert_eq!(
             format!("{:?}", url.kind),
@@ -222,12 +398,70 @@ mod tests {
             r#"InputFileKind::Path("/home/user/data/archive.zip")"#
         );
 
+        let local_path = InputFile::local_path("/var/lib/telegram-bot-api/archive.zip");
+        assert_eq!(
+            format!("{:?}", local_path.kind),
+            r#"InputFileKind::LocalPath("/var/lib/telegram-bot-api/archive.zip")"#
+        );
+        assert_eq!(
+            local_path.kind.get_text().unwrap(),
+            "file:///var/lib/telegram-bot-api/archive.zip"
+        );
+        assert_eq!(id.kind.get_text().unwrap(), "file-id");
+        assert!(path.kind.get_text().is_none());
+        assert_eq!(
+            to_file_uri(Path::new("/var/lib/telegram bot/#1?.zip")),
+            "file:///var/lib/telegram%20bot/%231%3F.zip"
+        );
+        assert_eq!(
+            to_file_uri(Path::new("archive.zip")),
+            Url::from_file_path(env::current_dir().unwrap().join("archive.zip"))
+                .unwrap()
+                .into_string()
+        );
+
         let reader = InputFileReader::from(Cursor::new(b"data")).info(("name", mime::TEXT_PLAIN));
+        assert_eq!(reader.get_info().unwrap().get_name(), "name");
         let reader = InputFile::from(reader);
         assert!(format!("{:?}", reader.kind).starts_with("InputFileKind::Reader("));
+        match reader.into_kind() {
+            InputFileKind::Reader(reader) => {
+                let (mut reader, info) = reader.into_inner();
+                let mut buf = String::new();
+                reader.read_to_string(&mut buf).unwrap();
+                assert_eq!(buf, "data");
+                assert_eq!(info.unwrap().get_mime_type().unwrap(), &mime::TEXT_PLAIN);
+            }
+            kind => panic!("Unexpected kind: {:?}", kind),
+        }
 
         let reader = InputFile::from(Cursor::new(b"data"));
         assert!(format!("{:?}", reader.kind).starts_with("InputFileKind::Reader("));
+
+        let stream = InputFileStream::new(futures::stream::iter_ok::<_, Error>(vec![b"data".to_vec()]))
+            .info("name")
+            .length(4);
+        assert_eq!(stream.get_info().unwrap().get_name(), "name");
+        assert_eq!(stream.get_length(), Some(4));
+        let stream = InputFile::from(stream);
+        assert_eq!(
+            format!("{:?}", stream.kind),
+            r#"InputFileKind::Stream(InputFileStream(stream: ..., info: Some(InputFileInfo { name: "name", mime_type: None }), length: Some(4)))"#
+        );
+        assert!(stream.try_clone().is_none());
+    }
+
+    #[test]
+    fn input_file_try_clone() {
+        let file = InputFile::file_id("file-id").try_clone().unwrap();
+        assert_eq!(format!("{:?}", file.kind), r#"InputFileKind::Id("file-id")"#);
+        let file = InputFile::url("url").try_clone().unwrap();
+        assert_eq!(format!("{:?}", file.kind), r#"InputFileKind::Url("url")"#);
+        let file = InputFile::path("path").try_clone().unwrap();
+        assert_eq!(format!("{:?}", file.kind), r#"InputFileKind::Path("path")"#);
+        let file = InputFile::local_path("/path").try_clone().unwrap();
+        assert_eq!(format!("{:?}", file.kind), r#"InputFileKind::LocalPath("/path")"#);
+        assert!(InputFile::from(Cursor::new(b"data")).try_clone().is_none());
     }
 
     #[test]
@@ -248,4 +482,12 @@ mod tests {
         assert_eq!(info.name, "name");
         assert_eq!(info.mime_type.unwrap(), mime::TEXT_PLAIN);
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_file_uri() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+        let path = Path::new(OsStr::from_bytes(b"/tmp/\xff.txt"));
+        assert_eq!(to_file_uri(path), "file:///tmp/%FF.txt");
+    }
 }
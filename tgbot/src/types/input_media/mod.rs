@@ -1,7 +1,4 @@
-use crate::{
-    request::FormValue,
-    types::{InputFile, InputFileKind},
-};
+use crate::{request::FormValue, types::InputFile};
 use failure::Error;
 use serde::Serialize;
 use std::collections::HashMap;
@@ -49,9 +46,9 @@ impl InputMedia {
 
     fn add_file<S: Into<String>>(&mut self, key: S, file: InputFile) -> String {
         let key = key.into();
-        match file.kind {
-            InputFileKind::Id(text) | InputFileKind::Url(text) => text,
-            _ => {
+        match file.kind.get_text() {
+            Some(text) => text,
+            None => {
                 self.fields.insert(key.clone(), file.into());
                 format!("attach://{}", key)
             }
@@ -1,6 +1,6 @@
 use crate::{
     request::FormValue,
-    types::{InputFile, InputFileKind, InputMediaPhoto, InputMediaVideo},
+    types::{InputFile, InputMediaPhoto, InputMediaVideo},
 };
 use failure::{Error, Fail};
 use serde::Serialize;
@@ -42,9 +42,9 @@ impl MediaGroup {
     }
 
     fn add_file(&mut self, file: InputFile) -> String {
-        match &file.kind {
-            InputFileKind::Id(text) | InputFileKind::Url(text) => text.clone(),
-            _ => {
+        match file.kind.get_text() {
+            Some(text) => text,
+            None => {
                 let idx = self.files.len();
                 let key = format!("tgbot_im_file_{}", idx);
                 self.files.insert(key.clone(), file);
@@ -34,6 +34,7 @@ where
                 description: required!(description),
                 error_code: raw.error_code,
                 parameters: raw.parameters,
+                attempts: None,
             })
         })
     }
@@ -52,6 +53,73 @@ pub struct ResponseError {
     pub error_code: Option<Integer>,
     /// Parameters
     pub parameters: Option<ResponseParameters>,
+    /// Number of attempts made according to [RetryPolicy](../struct.RetryPolicy.html)
+    ///
+    /// None when request is executed without a retry policy
+    pub attempts: Option<usize>,
+}
+
+impl ResponseError {
+    /// Returns a kind of the error
+    ///
+    /// Kind is derived from error code and description
+    pub fn kind(&self) -> ErrorKind {
+        let description = self.description.to_lowercase();
+        let contains = |pattern: &str| description.contains(pattern);
+        match self.error_code {
+            Some(401) => ErrorKind::Unauthorized,
+            Some(409) => ErrorKind::Conflict,
+            Some(429) => ErrorKind::TooManyRequests,
+            Some(403) if contains("bot was blocked by the user") => ErrorKind::BotBlocked,
+            Some(403) if contains("bot was kicked") => ErrorKind::BotKicked,
+            Some(403) if contains("user is deactivated") => ErrorKind::UserDeactivated,
+            Some(403) => ErrorKind::Forbidden,
+            _ if self.parameters.and_then(|p| p.migrate_to_chat_id).is_some() => ErrorKind::ChatMigrated,
+            Some(400) if contains("chat not found") => ErrorKind::ChatNotFound,
+            Some(400) if contains("message is not modified") => ErrorKind::MessageNotModified,
+            Some(400) if contains("message to edit not found") => ErrorKind::MessageToEditNotFound,
+            Some(400) if contains("message to delete not found") => ErrorKind::MessageToDeleteNotFound,
+            Some(400) => ErrorKind::BadRequest,
+            _ => ErrorKind::Unknown,
+        }
+    }
+}
+
+/// Kind of a response error
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ErrorKind {
+    /// Bot was blocked by the user
+    BotBlocked,
+    /// Bot was kicked from a group chat
+    BotKicked,
+    /// User is deactivated
+    UserDeactivated,
+    /// Chat not found
+    ChatNotFound,
+    /// Group has been migrated to a supergroup
+    ///
+    /// See `ResponseParameters::migrate_to_chat_id`
+    ChatMigrated,
+    /// New message content and reply markup are exactly the same as a current content and reply markup
+    MessageNotModified,
+    /// Message to edit not found
+    MessageToEditNotFound,
+    /// Message to delete not found
+    MessageToDeleteNotFound,
+    /// Flood control exceeded
+    ///
+    /// See `ResponseParameters::retry_after`
+    TooManyRequests,
+    /// Invalid bot token
+    Unauthorized,
+    /// Terminated by other getUpdates request or webhook is active
+    Conflict,
+    /// Any other bad request error
+    BadRequest,
+    /// Any other forbidden error
+    Forbidden,
+    /// Unknown error
+    Unknown,
 }
 
 /// Contains information about why a request was unsuccessful
@@ -131,4 +199,79 @@ mod tests {
             panic!("Unexpected response: {:?}", success);
         }
     }
+
+    #[test]
+    fn error_kind() {
+        fn kind(error_code: Option<Integer>, description: &str) -> ErrorKind {
+            ResponseError {
+                description: String::from(description),
+                error_code,
+                parameters: None,
+                attempts: None,
+            }
+            .kind()
+        }
+        assert_eq!(
+            kind(Some(403), "Forbidden: bot was blocked by the user"),
+            ErrorKind::BotBlocked
+        );
+        assert_eq!(
+            kind(Some(403), "Forbidden: bot was kicked from the group chat"),
+            ErrorKind::BotKicked
+        );
+        assert_eq!(
+            kind(Some(403), "Forbidden: user is deactivated"),
+            ErrorKind::UserDeactivated
+        );
+        assert_eq!(
+            kind(Some(403), "Forbidden: bot can't initiate conversation with a user"),
+            ErrorKind::Forbidden
+        );
+        assert_eq!(kind(Some(400), "Bad Request: chat not found"), ErrorKind::ChatNotFound);
+        assert_eq!(
+            kind(
+                Some(400),
+                "Bad Request: message is not modified: specified new message content \
+                 and reply markup are exactly the same as a current content and reply markup of the message"
+            ),
+            ErrorKind::MessageNotModified
+        );
+        assert_eq!(
+            kind(Some(400), "Bad Request: message to edit not found"),
+            ErrorKind::MessageToEditNotFound
+        );
+        assert_eq!(
+            kind(Some(400), "Bad Request: message to delete not found"),
+            ErrorKind::MessageToDeleteNotFound
+        );
+        assert_eq!(
+            kind(Some(400), "Bad Request: message text is empty"),
+            ErrorKind::BadRequest
+        );
+        assert_eq!(
+            kind(Some(429), "Too Many Requests: retry after 5"),
+            ErrorKind::TooManyRequests
+        );
+        assert_eq!(kind(Some(401), "Unauthorized"), ErrorKind::Unauthorized);
+        assert_eq!(
+            kind(
+                Some(409),
+                "Conflict: terminated by other getUpdates request; make sure that only one bot instance is running"
+            ),
+            ErrorKind::Conflict
+        );
+        assert_eq!(kind(Some(500), "Internal Server Error"), ErrorKind::Unknown);
+        assert_eq!(kind(None, "test err"), ErrorKind::Unknown);
+
+        let err = ResponseError {
+            description: String::from("Bad Request: group chat was upgraded to a supergroup chat"),
+            error_code: Some(400),
+            parameters: Some(ResponseParameters {
+                migrate_to_chat_id: Some(1),
+                retry_after: None,
+            }),
+            attempts: None,
+        };
+        assert_eq!(err.kind(), ErrorKind::ChatMigrated);
+    }
 }
@@ -1,4 +1,5 @@
 use dotenv::dotenv;
+use futures::Stream;
 use mockito::{mock, server_url};
 use tgbot::prelude::*;
 use tokio::runtime::current_thread::block_on_all;
@@ -14,3 +15,27 @@ fn poll() {
     print!("{:?}", data);
     assert_eq!(data, b"file-data");
 }
+
+#[test]
+fn stream() {
+    dotenv().ok();
+    let _m = mock("GET", "/file/bottoken/stream-path")
+        .with_body(b"file-data")
+        .create();
+    let _m_missing = mock("GET", "/file/bottoken/missing-path")
+        .with_status(404)
+        .with_body(r#"{"ok":false,"error_code":404,"description":"Not Found"}"#)
+        .create();
+    let api = Api::new(Config::new("token").host(server_url())).unwrap();
+
+    let chunks = api.download_file_stream("stream-path").expected_size(9).concat2();
+    assert_eq!(block_on_all(chunks).unwrap(), b"file-data");
+
+    let f = api.download_file_stream("stream-path").max_size(4).concat2();
+    let err = block_on_all(f).unwrap_err();
+    assert!(err.downcast::<DownloadError>().is_ok());
+
+    let f = api.download_file_stream("missing-path").concat2();
+    let err = block_on_all(f).unwrap_err();
+    assert_eq!(err.downcast::<ResponseError>().unwrap().error_code, Some(404));
+}
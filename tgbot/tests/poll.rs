@@ -1,7 +1,11 @@
 use dotenv::dotenv;
-use futures::Stream;
+use futures::{sync::oneshot, Future, Stream};
 use mockito::{mock, server_url, Matcher};
 use serde_json::json;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 use tgbot::prelude::*;
 use tokio::runtime::current_thread::block_on_all;
 
@@ -48,3 +52,143 @@ fn poll() {
     let update = &updates[0];
     assert_eq!(update.id, 1);
 }
+
+struct ShutdownHandler {
+    shutdown: Option<oneshot::Sender<()>>,
+    updates: Arc<Mutex<Vec<Integer>>>,
+}
+
+impl UpdateHandler for ShutdownHandler {
+    fn handle(&mut self, update: Update) {
+        self.updates.lock().unwrap().push(update.id);
+        if let Some(shutdown) = self.shutdown.take() {
+            shutdown.send(()).unwrap();
+        }
+    }
+}
+
+#[test]
+fn poll_shutdown() {
+    let update = |id| {
+        json!({
+            "update_id": id,
+            "message": {
+                "message_id": id,
+                "date": 0,
+                "from": {"id": 1, "is_bot": false, "first_name": "test"},
+                "chat": {"id": 1, "type": "private", "first_name": "test"},
+                "text": "test"
+            }
+        })
+    };
+    let fetch = mock("POST", "/bottoken/getUpdates")
+        .match_body(Matcher::Json(json!({
+            "offset": 1,
+            "limit": 100,
+            "timeout": 10,
+            "allowed_updates": []
+        })))
+        .with_body(serde_json::to_vec(&json!({"ok": true, "result": [update(1), update(2)]})).unwrap())
+        .expect(1)
+        .create();
+    let confirm = mock("POST", "/bottoken/getUpdates")
+        .match_body(Matcher::Json(json!({"offset": 3, "limit": 1, "timeout": 0})))
+        .with_body(serde_json::to_vec(&json!({"ok": true, "result": [update(3)]})).unwrap())
+        .expect(1)
+        .create();
+    let api = Api::new(Config::new("token").host(server_url())).unwrap();
+    let stream = UpdatesStream::from(api).should_retry(false);
+    let (shutdown_tx, shutdown_rx) = oneshot::channel();
+    let updates = Arc::new(Mutex::new(Vec::new()));
+    let handler = ShutdownHandler {
+        shutdown: Some(shutdown_tx),
+        updates: updates.clone(),
+    };
+    let method = UpdateMethod::poll(stream).shutdown_signal(shutdown_rx.map_err(|_| ()));
+    block_on_all(handle_updates(method, handler)).unwrap();
+    // buffered updates are handled, received update is not confirmed
+    assert_eq!(*updates.lock().unwrap(), vec![1, 2]);
+    fetch.assert();
+    confirm.assert();
+}
+
+#[test]
+fn poll_offset_store() {
+    let update = |id| {
+        json!({
+            "update_id": id,
+            "message": {
+                "message_id": id,
+                "date": 0,
+                "from": {"id": 1, "is_bot": false, "first_name": "test"},
+                "chat": {"id": 1, "type": "private", "first_name": "test"},
+                "text": "test"
+            }
+        })
+    };
+    let fetch = mock("POST", "/bottoken/getUpdates")
+        .match_body(Matcher::Json(json!({
+            "offset": 6,
+            "limit": 100,
+            "timeout": 10,
+            "allowed_updates": []
+        })))
+        .with_body(serde_json::to_vec(&json!({"ok": true, "result": [update(6), update(7)]})).unwrap())
+        .expect(2)
+        .create();
+    let api = Api::new(Config::new("token").host(server_url())).unwrap();
+    for (commit_policy, expected_offset) in &[(CommitPolicy::AfterHandle, 6), (CommitPolicy::AfterFetch, 7)] {
+        let store = MemoryOffsetStore::new();
+        block_on_all(store.save(5)).unwrap();
+        let stream = UpdatesStream::from(api.clone())
+            .should_retry(false)
+            .options(UpdatesStreamOptions::default().offset(1))
+            .offset_store(store.clone())
+            .commit_policy(*commit_policy);
+        // second update is returned, but not handled
+        let updates = block_on_all(stream.take(2).collect()).unwrap();
+        assert_eq!(updates.len(), 2);
+        assert_eq!(store.get_offset(), Some(*expected_offset));
+    }
+    fetch.assert();
+}
+
+#[test]
+fn poll_on_failure() {
+    let _m = mock("POST", "/bottoken/getUpdates")
+        .with_body(
+            serde_json::to_vec(&json!({
+                "ok": false,
+                "error_code": 429,
+                "description": "Too Many Requests",
+                "parameters": {"retry_after": 0}
+            }))
+            .unwrap(),
+        )
+        .create();
+    let api = Api::new(Config::new("token").host(server_url())).unwrap();
+    let failures = Arc::new(Mutex::new(Vec::new()));
+    let failures_callback = failures.clone();
+    let (shutdown_tx, shutdown_rx) = oneshot::channel();
+    let mut shutdown_tx = Some(shutdown_tx);
+    // retry_after is used instead of error timeout
+    let stream = UpdatesStream::from(api)
+        .options(UpdatesStreamOptions::default().error_timeout(60))
+        .on_failure(move |count, _err| {
+            failures_callback.lock().unwrap().push(count);
+            if count == 3 {
+                if let Some(shutdown_tx) = shutdown_tx.take() {
+                    shutdown_tx.send(()).unwrap();
+                }
+            }
+        });
+    let handler = ShutdownHandler {
+        shutdown: None,
+        updates: Arc::new(Mutex::new(Vec::new())),
+    };
+    let method = UpdateMethod::poll(stream)
+        .shutdown_signal(shutdown_rx.map_err(|_| ()))
+        .shutdown_timeout(Duration::from_secs(1));
+    block_on_all(handle_updates(method, handler)).unwrap();
+    assert_eq!(*failures.lock().unwrap(), vec![1, 2, 3]);
+}
@@ -0,0 +1,92 @@
+use rustls::{internal::pemfile, NoClientAuth, ServerConfig, ServerSession, StreamOwned};
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    sync::Arc,
+    thread,
+};
+use tgbot::{methods::GetMe, Api, Certificate, Config};
+use tokio::runtime::current_thread::block_on_all;
+
+const CA: &[u8] = include_bytes!("data/ca.pem");
+const SERVER_CERT: &[u8] = include_bytes!("data/localhost.pem");
+const SERVER_KEY: &[u8] = include_bytes!("data/localhost.key");
+const IP_CERT: &[u8] = include_bytes!("data/ip.pem");
+const IP_KEY: &[u8] = include_bytes!("data/ip.key");
+
+const RESPONSE: &str = r#"{"ok": true, "result": {"id": 1, "is_bot": true, "first_name": "test"}}"#;
+
+/// Starts a TLS server responding to each connection with a GetMe result
+///
+/// Returns a port
+fn start_server(cert: &[u8], key: &[u8]) -> u16 {
+    let mut config = ServerConfig::new(NoClientAuth::new());
+    let certs = pemfile::certs(&mut &cert[..]).unwrap();
+    let key = pemfile::pkcs8_private_keys(&mut &key[..]).unwrap().remove(0);
+    config.set_single_cert(certs, key).unwrap();
+    let config = Arc::new(config);
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = StreamOwned::new(ServerSession::new(&config), stream.unwrap());
+            let mut reader = BufReader::new(stream);
+            // handshake fails when client does not trust a certificate
+            let mut line = String::new();
+            while let Ok(len) = reader.read_line(&mut line) {
+                if len == 0 || line == "\r\n" {
+                    break;
+                }
+                line.clear();
+            }
+            let _ = write!(
+                reader.get_mut(),
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                RESPONSE.len(),
+                RESPONSE
+            );
+            let _ = reader.get_mut().flush();
+        }
+    });
+    port
+}
+
+#[test]
+fn custom_root_certificate() {
+    let port = start_server(SERVER_CERT, SERVER_KEY);
+    let host = format!("https://localhost:{}", port);
+
+    let config = Config::new("token")
+        .host(host.as_str())
+        .root_certificate(Certificate::from_pem(CA).unwrap());
+    let api = Api::new(config).unwrap();
+    let me = block_on_all(api.execute(GetMe)).unwrap();
+    assert_eq!(me.id, 1);
+
+    let api = Api::new(Config::new("token").host(host.as_str())).unwrap();
+    assert!(block_on_all(api.execute(GetMe)).is_err());
+}
+
+#[test]
+fn ip_host() {
+    let port = start_server(IP_CERT, IP_KEY);
+    let host = format!("https://127.0.0.1:{}", port);
+
+    let config = Config::new("token")
+        .host(host.as_str())
+        .root_certificate(Certificate::from_pem(IP_CERT).unwrap());
+    let api = Api::new(config).unwrap();
+    let me = block_on_all(api.execute(GetMe)).unwrap();
+    assert_eq!(me.id, 1);
+
+    let config = Config::new("token")
+        .host(host.as_str())
+        .root_certificate(Certificate::from_pem(CA).unwrap());
+    let api = Api::new(config).unwrap();
+    assert!(block_on_all(api.execute(GetMe)).is_err());
+}
+
+#[test]
+fn invalid_certificate() {
+    assert!(Certificate::from_pem(b"not a certificate").is_err());
+}
@@ -4,7 +4,12 @@ use failure::Error;
 use futures::{future, sync::oneshot::channel, Future, Stream};
 use hyper::{header::HeaderValue, Body, Client, Method, Request, Server, StatusCode};
 use log;
-use tgbot::prelude::*;
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+use tgbot::{prelude::*, Executor, RequestBody};
 use tokio::runtime::current_thread::block_on_all;
 
 struct Handler;
@@ -72,3 +77,265 @@ fn webhook() {
     assert_eq!(status, StatusCode::OK);
     assert_eq!(body, "");
 }
+
+fn post_update(
+    port: u16,
+    secret_token: Option<&'static str>,
+) -> impl Future<Item = (StatusCode, String), Error = Error> {
+    let mut req = Request::new(Body::from(
+        r#"{
+            "update_id":10000,
+            "message":{
+                "date":1441645532,
+                "chat":{"id":1111111,"first_name":"Test","type":"private"},
+                "message_id":1365,
+                "from":{"id":1111111,"first_name":"Test","is_bot":false},
+                "text":"/start"
+            }
+        }"#,
+    ));
+    *req.method_mut() = Method::POST;
+    *req.uri_mut() = format!("http://localhost:{}/updates", port).parse().unwrap();
+    if let Some(secret_token) = secret_token {
+        req.headers_mut().insert(
+            "X-Telegram-Bot-Api-Secret-Token",
+            HeaderValue::from_static(secret_token),
+        );
+    }
+    Client::new().request(req).map_err(Error::from).and_then(|res| {
+        let status = res.status();
+        res.into_body()
+            .concat2()
+            .map_err(Error::from)
+            .and_then(|body| String::from_utf8(body.into_iter().collect()).map_err(Error::from))
+            .map(move |body| (status, body))
+    })
+}
+
+#[test]
+fn webhook_secret_token() {
+    let (tx, rx) = channel::<()>();
+    let server = Server::bind(&([127, 0, 0, 1], 8082).into())
+        .serve(WebhookServiceFactory::new("/updates", Handler).secret_token("secret"))
+        .with_graceful_shutdown(rx)
+        .map_err(|e| log::error!("Server error: {}", e));
+    let statuses = block_on_all(future::lazy(|| {
+        tokio::spawn(server);
+        let status = |(status, _)| status;
+        post_update(8082, None)
+            .map(status)
+            .join3(
+                post_update(8082, Some("invalid")).map(status),
+                post_update(8082, Some("secret")).map(status),
+            )
+            .then(|res| {
+                let _ = tx.send(());
+                res
+            })
+    }))
+    .unwrap();
+    assert_eq!(
+        statuses,
+        (StatusCode::UNAUTHORIZED, StatusCode::UNAUTHORIZED, StatusCode::OK)
+    );
+}
+
+struct ApiMock {
+    calls: Arc<Mutex<Vec<String>>>,
+    fail: bool,
+}
+
+impl Executor for ApiMock {
+    fn execute(&self, req: tgbot::Request) -> Box<dyn Future<Item = Vec<u8>, Error = Error> + Send> {
+        let method = req.url.rsplit('/').next().unwrap().to_string();
+        let body = match req.body {
+            RequestBody::Json(data) => String::from_utf8(data).unwrap(),
+            _ => String::new(),
+        };
+        let response: &[u8] = match method.as_str() {
+            "getWebhookInfo" => {
+                br#"{"ok": true, "result": {
+                "url": "https://example.com/updates",
+                "has_custom_certificate": false,
+                "pending_update_count": 1,
+                "last_error_date": 1,
+                "last_error_message": "Connection refused"
+            }}"#
+            }
+            "setWebhook" if self.fail => br#"{"ok": false, "error_code": 400, "description": "Bad Request"}"#,
+            _ => br#"{"ok": true, "result": true}"#,
+        };
+        self.calls.lock().unwrap().push(format!("{} {}", method, body));
+        Box::new(future::ok(response.to_vec()))
+    }
+}
+
+#[test]
+fn webhook_registration() {
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let api = Api::with_executor(
+        "token",
+        ApiMock {
+            calls: calls.clone(),
+            fail: false,
+        },
+    );
+    let (tx, rx) = channel::<()>();
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(200));
+        let _ = tx.send(());
+    });
+    let method = UpdateMethod::webhook(([127, 0, 0, 1], 8083), "/updates")
+        .secret_token("secret")
+        .register(
+            WebhookRegistration::new(api, "https://example.com/updates")
+                .max_connections(10)
+                .delete_on_shutdown(true),
+        )
+        .shutdown_signal(rx.map_err(|_| ()));
+    block_on_all(handle_updates(method, Handler)).unwrap();
+    assert_eq!(
+        *calls.lock().unwrap(),
+        vec![
+            String::from(
+                r#"setWebhook {"url":"https://example.com/updates","max_connections":10,"secret_token":"secret"}"#
+            ),
+            String::from("getWebhookInfo "),
+            String::from("deleteWebhook "),
+        ]
+    );
+}
+
+#[test]
+fn webhook_registration_failed() {
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let api = Api::with_executor(
+        "token",
+        ApiMock {
+            calls: calls.clone(),
+            fail: true,
+        },
+    );
+    let method = UpdateMethod::webhook(([127, 0, 0, 1], 8086), "/updates")
+        .register(WebhookRegistration::new(api, "https://example.com/updates"));
+    assert!(block_on_all(handle_updates(method, Handler)).is_err());
+    assert_eq!(
+        *calls.lock().unwrap(),
+        vec![String::from(r#"setWebhook {"url":"https://example.com/updates"}"#)]
+    );
+}
+
+struct ReplyHandler;
+
+impl UpdateHandler for ReplyHandler {
+    fn handle(&mut self, _update: Update) {}
+
+    fn handle_with_reply(&mut self, update: Update, reply: WebhookReply) {
+        if let UpdateKind::Message(message) = update.kind {
+            reply.send(SendMessage::new(message.get_chat_id(), "pong")).unwrap();
+        }
+    }
+}
+
+#[test]
+fn webhook_reply() {
+    let (tx, rx) = channel::<()>();
+    let server = Server::bind(&([127, 0, 0, 1], 8084).into())
+        .serve(WebhookServiceFactory::new("/updates", ReplyHandler).reply_timeout(Duration::from_secs(5)))
+        .with_graceful_shutdown(rx)
+        .map_err(|e| log::error!("Server error: {}", e));
+    let (tx_empty, rx_empty) = channel::<()>();
+    let server_empty = Server::bind(&([127, 0, 0, 1], 8085).into())
+        .serve(WebhookServiceFactory::new("/updates", Handler).reply_timeout(Duration::from_secs(5)))
+        .with_graceful_shutdown(rx_empty)
+        .map_err(|e| log::error!("Server error: {}", e));
+    let ((status, body), (empty_status, empty_body)) = block_on_all(future::lazy(|| {
+        tokio::spawn(server);
+        tokio::spawn(server_empty);
+        post_update(8084, None).join(post_update(8085, None)).then(|res| {
+            let _ = tx.send(());
+            let _ = tx_empty.send(());
+            res
+        })
+    }))
+    .unwrap();
+    assert_eq!(status, StatusCode::OK);
+    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(
+        body,
+        serde_json::json!({"method": "sendMessage", "chat_id": 1111111, "text": "pong"})
+    );
+    assert_eq!(empty_status, StatusCode::OK);
+    assert_eq!(empty_body, "");
+}
+
+struct CountingHandler {
+    updates: Arc<Mutex<Vec<Integer>>>,
+}
+
+impl UpdateHandler for CountingHandler {
+    fn handle(&mut self, update: Update) {
+        self.updates.lock().unwrap().push(update.id);
+    }
+}
+
+#[test]
+fn webhook_deduplicate() {
+    let (tx, rx) = channel::<()>();
+    let updates = Arc::new(Mutex::new(Vec::new()));
+    let deduplicator = Deduplicator::new(10);
+    let method = UpdateMethod::webhook(([127, 0, 0, 1], 8086), "/updates")
+        .deduplicate(deduplicator.clone())
+        .shutdown_signal(rx.map_err(|_| ()));
+    let handler = CountingHandler {
+        updates: updates.clone(),
+    };
+    block_on_all(future::lazy(move || {
+        tokio::spawn(
+            post_update(8086, None)
+                .and_then(|_| post_update(8086, None))
+                .then(|res| {
+                    let _ = tx.send(());
+                    res.map(|_| ()).map_err(|e| log::error!("Request error: {}", e))
+                }),
+        );
+        handle_updates(method, handler)
+    }))
+    .unwrap();
+    assert_eq!(*updates.lock().unwrap(), vec![10000]);
+    assert_eq!(deduplicator.get_dropped_count(), 1);
+}
+
+#[test]
+fn webhook_status_routes() {
+    let (tx, rx) = channel::<()>();
+    let method = UpdateMethod::webhook(([127, 0, 0, 1], 8087), "/updates")
+        .queue_size(5)
+        .queue_overflow(QueueOverflow::Reject)
+        .status_routes(true)
+        .shutdown_signal(rx.map_err(|_| ()));
+    let handler = CountingHandler {
+        updates: Arc::new(Mutex::new(Vec::new())),
+    };
+    let stats = Arc::new(Mutex::new(None));
+    let stats_result = stats.clone();
+    block_on_all(future::lazy(move || {
+        let uri: hyper::Uri = "http://localhost:8087/stats".parse().unwrap();
+        tokio::spawn(
+            post_update(8087, None)
+                .and_then(|_| Client::new().get(uri).map_err(Error::from))
+                .and_then(|res| res.into_body().concat2().map_err(Error::from))
+                .then(move |res| {
+                    let _ = tx.send(());
+                    let body = res.map_err(|e| log::error!("Request error: {}", e))?;
+                    *stats_result.lock().unwrap() = serde_json::from_slice::<serde_json::Value>(&body).ok();
+                    Ok(())
+                }),
+        );
+        handle_updates(method, handler)
+    }))
+    .unwrap();
+    let stats = stats.lock().unwrap().take().unwrap();
+    assert_eq!(stats["queue_size"], 5);
+    assert_eq!(stats["rejected"], 0);
+}